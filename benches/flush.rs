@@ -0,0 +1,47 @@
+//! Indexing throughput into an on-disk db for a few --flush-interval values, run it with
+//! `cargo bench --bench flush`
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use felix::source::MemorySource;
+use felix::test_util::{cat_spend, chain, test_app_at};
+use felix::IndexOptions;
+
+const BLOCKS: u32 = 200;
+const CAT_TXS_PER_BLOCK: u32 = 10;
+
+fn flush_interval(c: &mut Criterion) {
+    // plus a tip, which is never indexed
+    let txs = (0..=BLOCKS)
+        .map(|height| {
+            (0..CAT_TXS_PER_BLOCK)
+                .map(|i| cat_spend(height * CAT_TXS_PER_BLOCK + i))
+                .collect()
+        })
+        .collect();
+    let source = MemorySource::new(0, chain(txs, 0));
+    let mut group = c.benchmark_group("flush_interval");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Elements(BLOCKS.into()));
+    for flush_interval in [1, 10, 100] {
+        let options = IndexOptions {
+            flush_interval,
+            ..Default::default()
+        };
+        group.bench_function(flush_interval.to_string(), |b| {
+            b.iter_batched(
+                || {
+                    let dir = tempfile::tempdir().unwrap();
+                    let app = test_app_at(&dir.path().join("db"), 0);
+                    (dir, app)
+                },
+                |(_dir, app)| app.start_index(&source, &options).unwrap(),
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, flush_interval);
+criterion_main!(benches);
@@ -0,0 +1,128 @@
+//! Errors returned by the library, so callers can match on specific failures
+
+use std::array::TryFromSliceError;
+use std::path::PathBuf;
+
+use bitcoin::{BlockHash, OutPoint};
+use plotters::drawing::DrawingAreaErrorKind;
+use thiserror::Error;
+
+use crate::Network;
+
+pub type Result<T, E = FelixError> = std::result::Result<T, E>;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum FelixError {
+    #[error("bitcoind rpc failed: {0}")]
+    Rpc(#[from] bitcoincore_rpc::Error),
+    #[error("failed to connect to bitcoind at {url}: {source}")]
+    Connect {
+        url: String,
+        source: bitcoincore_rpc::Error,
+    },
+    #[error("bitcoind at {url} is on chain {chain:?} but the configured network is {expected:?}")]
+    WrongNetwork {
+        url: String,
+        chain: String,
+        expected: Network,
+    },
+    #[error("db failed: {0}")]
+    Db(#[from] sled::Error),
+    #[error("failed to open db at {}: {source}", path.display())]
+    OpenDb { path: PathBuf, source: sled::Error },
+    /// A stored key that isn't a big endian height
+    #[error("malformed height key in the db: {0}")]
+    HeightKey(#[from] TryFromSliceError),
+    #[error("failed to (de)serialize: {0}")]
+    Serde(BoxError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("failed to create output directory {}: {source}", path.display())]
+    OutputDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("cannot write {}: {source}", path.display())]
+    OutputFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to write csv: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("failed to draw plot: {0}")]
+    Plot(BoxError),
+    #[error("failed to start the parser threads: {0}")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+    /// The chain reorganized below where indexing is allowed to roll back to
+    #[error("reorg at height {at}")]
+    Reorg { at: u64 },
+    #[error("no checkpoint stored, nothing has been indexed yet")]
+    CheckpointMissing,
+    #[error("height {height} is outside the indexable heights {start_block}..={tip}")]
+    CheckpointOutOfRange {
+        height: u64,
+        start_block: u64,
+        tip: u64,
+    },
+    #[error("prevout {0} doesn't exist")]
+    PrevoutMissing(OutPoint),
+    #[error("{0}")]
+    BlockFile(String),
+    /// The node no longer has the blocks indexing would start from
+    #[error("node is pruned below height {prune_height}, can't index from {start}, pick a later start block or an unpruned node")]
+    Pruned { start: u64, prune_height: u64 },
+    #[error("block {0} isn't on the node's active chain")]
+    InactiveBlock(BlockHash),
+    #[error("db was indexed from start block {stored} but {given} was given, pass --force to use it anyway")]
+    StartBlockMismatch { stored: u64, given: u64 },
+    #[error("the db already holds an index, import into an empty db")]
+    DbNotEmpty,
+    #[error("snapshot has format version {found}, this version of felix reads {supported}")]
+    SnapshotVersion { found: u64, supported: u64 },
+    #[error("report start {start} is above report end {end}")]
+    ReportRange { start: u64, end: u64 },
+    #[error("not connected to bitcoind, this command only reads the db")]
+    Offline,
+    #[error("invalid height range {0}, expected a non empty start:end")]
+    HeightRange(String),
+    #[error("invalid txid prefix {0}, expected at least 6 hex chars")]
+    TxidPrefix(String),
+    #[error("txid prefix {prefix} matches {matches} indexed txs")]
+    AmbiguousTxid { prefix: String, matches: usize },
+    #[error("invalid script query {0}, expected script hex or a run of opcodes")]
+    ScriptQuery(String),
+    #[error("invalid hex {0}")]
+    Hex(String),
+    #[error("unknown opcode: {0}")]
+    UnknownOpcode(String),
+    #[error("{0}")]
+    Unsupported(&'static str),
+    #[error("no index of {opcodes} in the db at {}", path.display())]
+    NoIndex { path: PathBuf, opcodes: String },
+}
+
+impl<T: std::fmt::Debug> From<ciborium::de::Error<T>> for FelixError {
+    fn from(err: ciborium::de::Error<T>) -> Self {
+        Self::Serde(err.to_string().into())
+    }
+}
+
+impl<T: std::fmt::Debug> From<ciborium::ser::Error<T>> for FelixError {
+    fn from(err: ciborium::ser::Error<T>) -> Self {
+        Self::Serde(err.to_string().into())
+    }
+}
+
+impl From<serde_json::Error> for FelixError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Serde(Box::new(err))
+    }
+}
+
+impl<E: std::error::Error + Send + Sync + 'static> From<DrawingAreaErrorKind<E>> for FelixError {
+    fn from(err: DrawingAreaErrorKind<E>) -> Self {
+        Self::Plot(Box::new(err))
+    }
+}
@@ -0,0 +1,4080 @@
+//! Index of the transactions whose tapscripts use OP_CAT, or any other set of opcodes
+
+pub mod error;
+pub mod metrics;
+pub mod rpc;
+pub mod server;
+pub mod source;
+#[doc(hidden)]
+pub mod test_util;
+pub mod webhook;
+pub mod witness;
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::{BufWriter, IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::Scope;
+use std::time::{Duration, Instant};
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::opcodes::all::{OP_CAT, OP_NOP4, OP_RETURN_203, OP_RETURN_204};
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::Instruction;
+use bitcoin::{
+    Amount, Block, BlockHash, OutPoint, Script, Transaction, TxIn, TxOut, Txid, Witness,
+};
+pub use bitcoincore_rpc::Auth;
+use bitcoincore_rpc::RpcApi;
+use chrono::DateTime;
+use clap::ValueEnum;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info, warn};
+use lru::LruCache;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+pub use crate::error::{FelixError, Result};
+use crate::metrics::Metrics;
+use crate::rpc::FailoverRpc;
+use crate::source::BlockSource;
+use crate::webhook::Webhook;
+use crate::witness::WitnessScript;
+
+/// `Config::db_path` opening a temporary db that's gone once the process exits
+pub const IN_MEMORY_DB_PATH: &str = ":memory:";
+/// Sled tree holding the single keys below for an index in a namespace, see `namespace`
+const META_TREE: &str = "meta";
+/// Sled key for checkpoint, the next height to be indexed
+const CHECKPOINT_SLED_KEY: &str = "CHECKPOINT";
+/// Sled key for the checkpoint format. DBs created before it existed stored the last indexed
+/// height as the checkpoint instead of the next one
+const CHECKPOINT_VERSION_SLED_KEY: &str = "CHECKPOINT_VERSION";
+const CHECKPOINT_VERSION: u64 = 1;
+/// Sled tree holding the block hash of every indexed height, keyed by big endian height
+const HASHES_TREE: &str = "hashes";
+/// Number of prevout transactions kept around for --strict-prevout-check
+const PREVOUT_CACHE_SIZE: usize = 1024;
+/// Without a terminal to draw the progress bar on, log progress every this many blocks instead
+const PROGRESS_LOG_INTERVAL: u64 = 1000;
+/// Sled tree caching the header timestamp of every indexed height, keyed by big endian height
+const BLOCK_TIMES_TREE: &str = "block_times";
+/// Key path and script path spend counts by height, for blocks indexed with
+/// --index-all-taproot
+const TAPROOT_SPENDS_TREE: &str = "taproot_spends";
+/// Outputs spent by the inputs of the txs indexed with --store-prevouts, keyed by the txid of the
+/// output followed by its big endian vout
+const PREVOUTS_TREE: &str = "prevouts";
+/// Sled key for the start block the db was first indexed from
+const START_BLOCK_SLED_KEY: &str = "START_BLOCK";
+/// Sled key for the running total of indexed cat txs
+const TOTAL_CAT_TXS_SLED_KEY: &str = "TOTAL_CAT_TXS";
+/// Sled tree holding the txids indexed at every height with cat txs, keyed by big endian height
+const HEIGHTS_TREE: &str = "heights";
+/// Sled tree holding a TransactionExt for every indexed transaction, keyed by txid
+const TXS_TREE: &str = "txs";
+/// Sled tree holding the lowest height every indexed tapscript was spent at, keyed by its hex
+const FIRST_SEEN_TREE: &str = "first_seen";
+/// Sled key for the height the last jsonl report stopped before, where `--append` picks up
+const REPORT_END_SLED_KEY: &str = "REPORT_END";
+/// Written next to the reports, describing how the last one was generated
+const MANIFEST_FILE: &str = "manifest.json";
+/// Sled key for the SyncSummary of the last indexing run
+const LAST_SYNC_SLED_KEY: &str = "LAST_SYNC";
+/// Sled key for the IndexFilter the index was last built with
+const INDEX_FILTER_SLED_KEY: &str = "INDEX_FILTER";
+/// Shortest txid prefix `App::find_txs` looks up
+const MIN_TXID_PREFIX_LEN: usize = 6;
+/// First byte of a zstd compressed value in the txs tree. A CBOR item can't start with a break,
+/// so uncompressed values written before --compress are told apart
+const COMPRESSED_MAGIC: u8 = 0xff;
+/// Format of the files written by `App::export_snapshot`, bumped on incompatible changes
+const SNAPSHOT_VERSION: u64 = 1;
+/// Opcodes proposed by soft forks that the bitcoin crate only knows by their current meaning.
+/// The first name for an opcode is the one reported
+const OPCODE_ALIASES: &[(&str, Opcode)] = &[
+    ("OP_CHECKTEMPLATEVERIFY", OP_NOP4),
+    ("OP_CTV", OP_NOP4),
+    ("OP_INTERNALKEY", OP_RETURN_203),
+    ("OP_CHECKSIGFROMSTACK", OP_RETURN_204),
+    ("OP_CSFS", OP_RETURN_204),
+];
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+/// Options of the `index` command
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct IndexOptions {
+    /// index exactly the heights start:end (end exclusive) and exit, without reading or writing
+    /// the checkpoint
+    #[arg(long, value_parser = parse_height_range)]
+    pub range: Option<Range<u64>>,
+
+    /// index from this height instead of the stored checkpoint, for this run only
+    #[arg(long, conflicts_with = "range")]
+    pub start_from: Option<u64>,
+
+    /// keep following the tip once indexing caught up
+    #[arg(long)]
+    pub follow: bool,
+
+    /// seconds to wait between polls for new blocks in follow mode
+    #[arg(long, default_value = "60")]
+    pub poll_interval: u64,
+
+    /// scan the blocks and log how many cat txs they hold without writing anything to the db
+    #[arg(long, conflicts_with = "follow")]
+    pub dry_run: bool,
+
+    /// index at most this many blocks and exit, the checkpoint advances as usual so the next run
+    /// picks up where this one stopped
+    #[arg(long, conflicts_with = "follow")]
+    pub max_blocks: Option<u64>,
+
+    /// blocks indexed between flushes of the db to disk. A crash loses at most the unflushed
+    /// blocks, which are indexed again since the checkpoint is flushed with them
+    #[arg(long, default_value = "100")]
+    pub flush_interval: u64,
+
+    /// also count the key path and script path spends of every block, for the taproot stats.
+    /// Every input of every tx gets looked at, not just the ones using the opcodes
+    #[arg(long)]
+    pub index_all_taproot: bool,
+
+    /// look up the outputs the indexed txs spend in bitcoind to work out their fees, one request
+    /// per spent tx. Without it fees only come from prevouts stored with --store-prevouts
+    #[arg(long)]
+    pub fees: bool,
+
+    /// store the output every input of the indexed txs spends, so their fees can be worked out
+    /// from the db alone, e.g. when indexing again from --block-files
+    #[arg(long)]
+    pub store_prevouts: bool,
+
+    /// POST the height and txids of every indexed block with cat txs to this url as json
+    #[arg(long)]
+    pub webhook: Option<String>,
+
+    #[command(flatten)]
+    pub filter: IndexFilter,
+}
+
+/// Thresholds a matching tx has to meet to be indexed, recorded in the db so queries can tell the
+/// index is filtered
+#[derive(clap::Args, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexFilter {
+    /// only index txs with at least this many inputs
+    #[arg(long)]
+    pub min_inputs: Option<usize>,
+
+    /// only index txs with at least this many outputs
+    #[arg(long)]
+    pub min_outputs: Option<usize>,
+}
+
+impl IndexFilter {
+    pub fn keeps(&self, tx: &Transaction) -> bool {
+        self.min_inputs.is_none_or(|min| tx.input.len() >= min)
+            && self.min_outputs.is_none_or(|min| tx.output.len() >= min)
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+/// Options of the `plot` command
+#[derive(clap::Args, Debug, Clone)]
+pub struct PlotOptions {
+    /// what the x axis of `plot` shows
+    #[arg(long, value_enum, default_value = "height")]
+    pub x_axis: XAxis,
+
+    /// what `plot` charts per block
+    #[arg(long, value_enum, default_value = "txs")]
+    pub metric: Metric,
+
+    /// file `plot` writes to instead of total_cat_txs.png in the output directory
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+
+    /// fixed upper bound of the y axis, by default it's scaled to the data
+    #[arg(long)]
+    pub y_max: Option<u64>,
+
+    /// image format `plot` writes
+    #[arg(long, value_enum, default_value = "png")]
+    pub plot_format: PlotFormat,
+
+    /// whether `plot` charts each block or the running total
+    #[arg(long, value_enum, default_value = "per-block")]
+    pub plot_mode: PlotMode,
+}
+
+/// Options of the `generate_report` command
+#[derive(clap::Args, Debug, Clone)]
+pub struct ReportOptions {
+    /// output format of `generate_report`
+    #[arg(long, value_enum, default_value = "json")]
+    pub format: ReportFormat,
+
+    /// first height included in the report, defaults to the start block
+    #[arg(long)]
+    pub report_start: Option<u64>,
+
+    /// height the report stops before, defaults to the checkpoint
+    #[arg(long)]
+    pub report_end: Option<u64>,
+
+    /// report every distinct tapscript with the txids spending it instead of every tx
+    #[arg(long)]
+    pub group_by_script: bool,
+
+    /// only add the blocks indexed since the last jsonl report to its file
+    #[arg(long, conflicts_with_all = ["report_start", "group_by_script"])]
+    pub append: bool,
+
+    /// only report txs with a tapscript that failed to parse, to malformed_scripts files
+    #[arg(long, conflicts_with_all = ["append", "group_by_script"])]
+    pub malformed: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlotMode {
+    PerBlock,
+    Cumulative,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlotFormat {
+    Png,
+    Svg,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+    /// json and csv
+    Both,
+    /// one json TransactionExt per line, streamed block by block
+    Jsonl,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XAxis {
+    Height,
+    Time,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// transactions using CAT
+    Txs,
+    /// OP_CAT opcodes executed by those transactions
+    CatOps,
+    /// median fee rate in sat/vB of those transactions. Fees are only known for an index built
+    /// with --fees or --store-prevouts
+    Feerate,
+    /// median byte length of the tapscripts of those transactions
+    ScriptSize,
+}
+
+/// How `find_script` compares indexed tapscripts against the query
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// the query is the hex of the whole script
+    Exact,
+    /// the query is a space separated run of opcodes somewhere in the script
+    Contains,
+    /// the script begins with the run of opcodes
+    StartsWith,
+    /// the script ends with the run of opcodes
+    EndsWith,
+}
+
+impl Network {
+    /// Height indexing starts from when no start block is given
+    pub fn default_start_block(self) -> u64 {
+        match self {
+            // taproot activation
+            Network::Mainnet => 709_632,
+            // taproot had no activation delay on testnet, scan the whole chain
+            Network::Testnet => 0,
+            // OP_CAT activation on inquisition signet
+            Network::Signet => 193_536,
+            Network::Regtest => 0,
+        }
+    }
+
+    /// Name of the network as reported in the `chain` field of `getblockchaininfo`
+    pub fn chain_name(self) -> &'static str {
+        match self {
+            Network::Mainnet => "main",
+            Network::Testnet => "test",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        }
+    }
+}
+
+/// Everything `App` needs, already resolved from flags, config files and defaults
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// e.g. http://127.0.0.1:38332, later ones are failed over to when the first is unreachable
+    pub bitcoind_urls: Vec<String>,
+    pub bitcoind_auth: Auth,
+    /// network bitcoind is expected to run on
+    pub network: Network,
+    /// defaults to the one stored in the db, or a per network height for a new db
+    pub start_block: Option<u64>,
+    /// resolved to the height of the block on the active chain and used as `start_block`
+    pub start_block_hash: Option<BlockHash>,
+    /// use `start_block` even though the db was indexed from another one
+    pub force: bool,
+    /// IN_MEMORY_DB_PATH for a temporary db
+    pub db_path: PathBuf,
+    /// only open the db, for commands that don't need bitcoind. The bitcoind urls and auth are
+    /// ignored
+    pub offline: bool,
+    /// blocks below the tip indexing stops at, to stay clear of reorgs, even signet ones
+    pub confirmations: u64,
+    /// number of blocks fetched from bitcoind concurrently ahead of the parser
+    pub rpc_concurrency: u64,
+    /// how often a bitcoind request failing with a transient error is retried
+    pub rpc_max_retries: u32,
+    /// threads scanning a block's transactions, 0 uses one per core
+    pub parse_threads: usize,
+    /// opcodes a tapscript has to use to be indexed
+    pub opcodes: Vec<Opcode>,
+    /// confirm with bitcoind that the prevout of every matching input is P2TR
+    pub strict_prevout_check: bool,
+    /// only index spends whose witness carries an annex
+    pub with_annex: bool,
+    /// look for the opcodes in every witness element parsing as a script, not just tapscripts
+    pub scan_all_witness_items: bool,
+    /// directory reports and plots are written to
+    pub output_dir: PathBuf,
+    /// zstd compress the txs written to the db, either kind is read back
+    pub compress: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionExt {
+    pub height: u64,
+    // block header timestamp
+    #[serde(default)]
+    pub block_time: u32,
+    // tx size
+    pub size: u64,
+    // tapscript of every input using an indexed opcode
+    #[serde(default)]
+    pub inputs: Vec<InputTapscript>,
+    // indices of the inputs using an indexed opcode, ascending. Empty for txs indexed before it
+    // was stored, see `matched_inputs()`
+    #[serde(default)]
+    pub matched_inputs: Vec<u32>,
+    // human readable tapscript of each entry in `inputs`, kept for older consumers
+    pub scripts_asm: Vec<String>,
+    // tapscript as hex of each entry in `inputs`, kept for older consumers
+    pub scripts_hex: Vec<String>,
+    // names of the indexed opcodes the tapscripts use
+    pub opcodes: Vec<String>,
+    // OP_CAT opcodes across all tapscripts
+    #[serde(default)]
+    pub cat_ops: usize,
+    // virtual size in vbytes
+    #[serde(default)]
+    pub vsize: u64,
+    // fee in sats, unknown unless indexed with --fees or stored prevouts, or when a prevout
+    // couldn't be fetched
+    #[serde(default)]
+    pub fee: Option<u64>,
+    // some tapscript failed to parse partway, e.g. on a truncated push, so its asm is incomplete
+    #[serde(default)]
+    pub malformed: bool,
+    // script type of every output, empty for txs indexed before it was stored
+    #[serde(default)]
+    pub output_types: Vec<OutputType>,
+    pub tx: Transaction,
+}
+
+impl TransactionExt {
+    /// Witness elements of a matched input once the annex is stripped, derived from the tx for
+    /// inputs indexed before it was stored
+    pub fn witness_items(&self, input: &InputTapscript) -> Option<u32> {
+        input.witness_items.or_else(|| {
+            self.tx
+                .input
+                .get(input.input as usize)
+                .map(|txin| witness::item_count(&txin.witness) as u32)
+        })
+    }
+
+    /// Fee rate in sat/vB, if the fee is known
+    pub fn fee_rate(&self) -> Option<f64> {
+        let vsize = self.tx.vsize() as f64;
+        self.fee.map(|fee| fee as f64 / vsize)
+    }
+
+    /// Indices of the inputs using an indexed opcode, derived from `inputs` if the tx was indexed
+    /// before they were stored
+    pub fn matched_inputs(&self) -> Vec<u32> {
+        if !self.matched_inputs.is_empty() {
+            return self.matched_inputs.clone();
+        }
+        let mut matched: Vec<u32> = self.inputs.iter().map(|input| input.input).collect();
+        matched.dedup();
+        matched
+    }
+
+    /// Script type of every output, derived from the tx if it was indexed before they were stored
+    pub fn output_types(&self) -> Vec<OutputType> {
+        if !self.output_types.is_empty() {
+            return self.output_types.clone();
+        }
+        self.tx
+            .output
+            .iter()
+            .map(|output| OutputType::of(&output.script_pubkey))
+            .collect()
+    }
+}
+
+/// Kind of script an output pays to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputType {
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    OpReturn,
+    Other,
+}
+
+impl OutputType {
+    pub fn of(script_pubkey: &Script) -> Self {
+        if script_pubkey.is_p2pkh() {
+            OutputType::P2pkh
+        } else if script_pubkey.is_p2sh() {
+            OutputType::P2sh
+        } else if script_pubkey.is_p2wpkh() {
+            OutputType::P2wpkh
+        } else if script_pubkey.is_p2wsh() {
+            OutputType::P2wsh
+        } else if script_pubkey.is_p2tr() {
+            OutputType::P2tr
+        } else if script_pubkey.is_op_return() {
+            OutputType::OpReturn
+        } else {
+            OutputType::Other
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputType::P2pkh => "p2pkh",
+            OutputType::P2sh => "p2sh",
+            OutputType::P2wpkh => "p2wpkh",
+            OutputType::P2wsh => "p2wsh",
+            OutputType::P2tr => "p2tr",
+            OutputType::OpReturn => "op_return",
+            OutputType::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputTapscript {
+    // index of the input in the transaction
+    pub input: u32,
+    pub asm: String,
+    pub hex: String,
+    // leaf version from the control block, unknown for inputs indexed before it was stored
+    #[serde(default)]
+    pub leaf_version: Option<u8>,
+    // annex as hex including its 0x50 prefix, if the witness carried one
+    #[serde(default)]
+    pub annex: Option<String>,
+    // index of the script among the witness elements, unknown for inputs indexed before it was
+    // stored
+    #[serde(default)]
+    pub witness_index: Option<u32>,
+    // byte length of the script, unknown for inputs indexed before it was stored
+    #[serde(default)]
+    pub size: Option<u32>,
+    // witness elements of the input once the annex is stripped, unknown for inputs indexed
+    // before it was stored
+    #[serde(default)]
+    pub witness_items: Option<u32>,
+}
+
+impl InputTapscript {
+    /// Byte length of the script, derived from its hex for inputs indexed before it was stored
+    pub fn size(&self) -> u32 {
+        self.size.unwrap_or(self.hex.len() as u32 / 2)
+    }
+}
+
+/// Provenance of a report, see `App::generate_cat_report`
+#[derive(Debug, Serialize)]
+struct ReportManifest {
+    felix_version: &'static str,
+    network: &'static str,
+    opcodes: Vec<String>,
+    /// heights the report covers, for an appended report only the ones added this time
+    start_height: u64,
+    end_height: u64,
+    checkpoint: u64,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestFile {
+    name: String,
+    sha256: String,
+}
+
+/// One line of the csv report
+#[derive(Debug, Serialize)]
+struct CsvRecord {
+    height: u64,
+    txid: String,
+    input_count: usize,
+    /// space separated
+    matched_inputs: String,
+    matched_opcode: String,
+    tapscript_hex_len: usize,
+    vsize: usize,
+    fee: Option<u64>,
+}
+
+impl From<&TransactionExt> for CsvRecord {
+    fn from(tx_ext: &TransactionExt) -> Self {
+        Self {
+            height: tx_ext.height,
+            txid: tx_ext.tx.compute_txid().to_string(),
+            input_count: tx_ext.tx.input.len(),
+            matched_inputs: tx_ext
+                .matched_inputs()
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(" "),
+            matched_opcode: tx_ext.opcodes.join(" "),
+            tapscript_hex_len: tx_ext.scripts_hex.iter().map(String::len).sum(),
+            vsize: tx_ext.tx.vsize(),
+            fee: tx_ext.fee,
+        }
+    }
+}
+
+/// A distinct tapscript and the txs spending it, see `ReportOptions::group_by_script`
+#[derive(Debug, Serialize)]
+struct ScriptGroup {
+    tapscript_hex: String,
+    tapscript_asm: String,
+    /// inputs spending the tapscript, a tx can spend it more than once
+    spends: u64,
+    txids: Vec<String>,
+}
+
+/// One line of the csv report grouped by tapscript
+#[derive(Debug, Serialize)]
+struct CsvScriptGroup<'a> {
+    tapscript_hex: &'a str,
+    spends: u64,
+    tx_count: usize,
+    /// space separated
+    txids: String,
+}
+
+impl<'a> From<&'a ScriptGroup> for CsvScriptGroup<'a> {
+    fn from(group: &'a ScriptGroup) -> Self {
+        Self {
+            tapscript_hex: &group.tapscript_hex,
+            spends: group.spends,
+            tx_count: group.txids.len(),
+            txids: group.txids.join(" "),
+        }
+    }
+}
+
+/// Summary of the whole index, see `App::compute_stats`
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    /// first indexed height
+    pub start_height: u64,
+    /// height indexing will continue from
+    pub end_height: u64,
+    pub total_cat_txs: u64,
+    pub blocks_with_cats: u64,
+    pub busiest_block_height: Option<u64>,
+    pub busiest_block_cat_txs: u64,
+    pub avg_cat_txs_per_block_with_cats: f64,
+    pub blocks_without_cats: u64,
+    /// share of the indexed blocks without cat txs, in percent
+    pub blocks_without_cats_percent: f64,
+    /// first height of the longest run of consecutive blocks without cat txs
+    pub longest_run_without_cats_start: Option<u64>,
+    pub longest_run_without_cats: u64,
+    /// thresholds the indexed txs had to meet, if any
+    pub filter: Option<IndexFilter>,
+    /// number of cat txs by how many OP_CATs their tapscripts use
+    pub cat_ops_histogram: BTreeMap<usize, u64>,
+    /// number of indexed tapscript spends by the leaf version of their control block
+    pub leaf_versions: BTreeMap<String, u64>,
+    /// number of outputs of cat txs by their script type
+    pub output_types: BTreeMap<OutputType, u64>,
+    /// indexed tapscript spends whose witness carried an annex
+    pub annex_spends: u64,
+    /// average annex size in bytes, including its 0x50 prefix
+    pub avg_annex_size: f64,
+    /// number of indexed tapscript spends by how many witness elements they had, annex excluded
+    pub witness_items_histogram: BTreeMap<u32, u64>,
+    /// None unless blocks were indexed with --index-all-taproot
+    pub taproot: Option<TaprootStats>,
+    /// byte lengths of the indexed tapscripts, None when nothing is indexed
+    pub script_sizes: Option<ScriptSizes>,
+    /// bytes the txs take up in the db
+    pub stored_tx_bytes: u64,
+    /// bytes the txs would take up uncompressed
+    pub uncompressed_tx_bytes: u64,
+    /// uncompressed over stored bytes, 1 when nothing is compressed
+    pub compression_ratio: f64,
+}
+
+impl Stats {
+    pub fn log_summary(&self) {
+        info!("height range: {}..{}", self.start_height, self.end_height);
+        if let Some(filter) = &self.filter {
+            info!("index is filtered: {:?}", filter);
+        }
+        info!("total cat txs: {}", self.total_cat_txs);
+        info!("blocks with cat txs: {}", self.blocks_with_cats);
+        if let Some(height) = self.busiest_block_height {
+            info!(
+                "busiest block: {} with {} cat txs",
+                height, self.busiest_block_cat_txs
+            );
+        }
+        info!(
+            "avg cat txs per block with cat txs: {:.2}",
+            self.avg_cat_txs_per_block_with_cats
+        );
+        info!(
+            "blocks without cat txs: {} ({:.2}%)",
+            self.blocks_without_cats, self.blocks_without_cats_percent
+        );
+        if let Some(start) = self.longest_run_without_cats_start {
+            info!(
+                "longest run without cat txs: {} blocks from {}",
+                self.longest_run_without_cats, start
+            );
+        }
+        for (cat_ops, txs) in self.cat_ops_histogram.iter() {
+            info!("txs with {} OP_CATs: {}", cat_ops, txs);
+        }
+        for (items, spends) in self.witness_items_histogram.iter() {
+            info!("spends with {} witness elements: {}", items, spends);
+        }
+        for (leaf_version, spends) in self.leaf_versions.iter() {
+            info!("spends with leaf version {}: {}", leaf_version, spends);
+        }
+        for (output_type, outputs) in self.output_types.iter() {
+            info!("{} outputs: {}", output_type.name(), outputs);
+        }
+        info!(
+            "spends with an annex: {}, avg annex size: {:.2} bytes",
+            self.annex_spends, self.avg_annex_size
+        );
+        if let Some(taproot) = &self.taproot {
+            info!(
+                "taproot spends over {} blocks, key path: {}, script path: {}",
+                taproot.blocks, taproot.key_path_spends, taproot.script_path_spends
+            );
+            if let Some(ratio) = taproot.script_to_key_path_ratio {
+                info!("script path to key path ratio: {:.4}", ratio);
+            }
+            if let Some(ratio) = taproot.avg_block_ratio {
+                info!("avg script path to key path ratio per block: {:.4}", ratio);
+            }
+            for (leaf_version, spends) in taproot.leaf_versions.iter() {
+                info!(
+                    "script path spends with leaf version {}: {}",
+                    leaf_version, spends
+                );
+            }
+        }
+        if let Some(sizes) = &self.script_sizes {
+            info!(
+                "tapscript bytes min: {}, median: {}, p95: {}, max: {}",
+                sizes.min, sizes.median, sizes.p95, sizes.max
+            );
+        }
+        info!(
+            "tx bytes stored: {}, uncompressed: {}, ratio: {:.2}",
+            self.stored_tx_bytes, self.uncompressed_tx_bytes, self.compression_ratio
+        );
+    }
+}
+
+/// Taproot spends of a block, whether or not they use the opcodes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaprootSpends {
+    pub key_path: u64,
+    pub script_path: u64,
+    /// script path spends by the leaf version of their control block, including the reserved
+    /// ones. Empty for blocks counted before it was stored
+    #[serde(default)]
+    pub leaf_versions: BTreeMap<u8, u64>,
+}
+
+impl TaprootSpends {
+    fn of(block: &Block) -> Self {
+        let mut spends = Self::default();
+        for input in block.txdata.iter().flat_map(|tx| tx.input.iter()) {
+            if let Some(spend) = witness::parse_script_path(&input.witness) {
+                spends.script_path += 1;
+                *spends.leaf_versions.entry(spend.leaf_version).or_default() += 1;
+            } else if witness::is_key_path_spend(&input.witness) {
+                spends.key_path += 1;
+            }
+        }
+        spends
+    }
+}
+
+/// Key path and script path spends over the blocks indexed with --index-all-taproot, see
+/// `Stats`
+#[derive(Debug, Default, Serialize)]
+pub struct TaprootStats {
+    pub blocks: u64,
+    pub key_path_spends: u64,
+    pub script_path_spends: u64,
+    /// script path over key path spends, None without key path spends
+    pub script_to_key_path_ratio: Option<f64>,
+    /// mean of the per block ratios, over the blocks with key path spends
+    pub avg_block_ratio: Option<f64>,
+    /// script path spends of any leaf version, indexed or not, by leaf version
+    pub leaf_versions: BTreeMap<String, u64>,
+}
+
+/// Distribution of tapscript byte lengths, see `Stats`
+#[derive(Debug, Serialize)]
+pub struct ScriptSizes {
+    pub min: u32,
+    pub median: u32,
+    pub p95: u32,
+    pub max: u32,
+}
+
+impl ScriptSizes {
+    fn of(mut sizes: Vec<u32>) -> Option<Self> {
+        sizes.sort_unstable();
+        Some(Self {
+            min: *sizes.first()?,
+            median: percentile(&sizes, 50),
+            p95: percentile(&sizes, 95),
+            max: *sizes.last()?,
+        })
+    }
+}
+
+/// Detector throughput over a range of blocks, see `App::bench_detector`
+#[derive(Debug, Default, Serialize)]
+pub struct BenchSummary {
+    pub blocks: u64,
+    pub txs: u64,
+    /// txs with an input using the indexed opcodes
+    pub matches: u64,
+    /// serialized size of every witness scanned
+    pub witness_bytes: u64,
+    pub seconds: f64,
+    pub txs_per_sec: f64,
+    pub mb_per_sec: f64,
+}
+
+/// An unconfirmed tx using the indexed opcodes, see `App::scan_mempool`
+#[derive(Debug, Serialize)]
+pub struct MempoolTx {
+    pub txid: String,
+    pub opcodes: Vec<String>,
+    pub inputs: Vec<InputTapscript>,
+    pub vsize: u64,
+}
+
+/// Outcome of `App::repair_check_point`
+#[derive(Debug, Serialize)]
+pub struct CheckpointRepair {
+    /// checkpoint stored before the repair, if there was one
+    pub previous: Option<u64>,
+    pub checkpoint: u64,
+    /// missing heights between indexed ones, the checkpoint is the start of the first
+    pub gaps: Vec<HeightGap>,
+}
+
+/// Heights `start..end` missing from the index
+#[derive(Debug, Serialize)]
+pub struct HeightGap {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// How an indexing run went, see `App::last_sync`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncSummary {
+    /// unix timestamps
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// heights indexed, end exclusive
+    pub start_height: u64,
+    pub end_height: u64,
+    /// blocks indexed, including any indexed again after a reorg
+    pub blocks: u64,
+    pub seconds: f64,
+    pub blocks_per_sec: f64,
+    pub cat_txs: u64,
+    /// matching txs before --min-inputs and --min-outputs were applied
+    #[serde(default)]
+    pub candidates: u64,
+}
+
+/// Number of cat txs indexed at a height
+#[derive(Debug, Serialize)]
+pub struct BlockCount {
+    pub height: u64,
+    pub count: u64,
+}
+
+/// A tapscript and the height it was first spent at, see `App::novel_scripts`
+#[derive(Debug, Serialize)]
+pub struct NovelScript {
+    pub height: u64,
+    pub tapscript_hex: String,
+}
+
+/// Outcome of comparing the stored block hashes against bitcoind, see `App::verify`
+#[derive(Debug, Default, Serialize)]
+pub struct VerifySummary {
+    pub matched: u64,
+    /// heights whose stored hash is no longer on the active chain
+    pub mismatched: Vec<u64>,
+    /// indexed heights without a stored hash
+    pub missing: u64,
+}
+
+/// Outcome of comparing the txids indexed by two dbs, see `App::diff`
+#[derive(Debug, Serialize)]
+pub struct DbDiff {
+    /// first height indexed by both dbs
+    pub start: u64,
+    /// height both dbs have indexed up to
+    pub end: u64,
+    /// heights whose txids differ
+    pub differing: Vec<u64>,
+    /// txids only indexed by this db
+    pub only_here: u64,
+    /// txids only indexed by the other db
+    pub only_other: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<HeightDiff>,
+}
+
+/// The txids only one of two dbs indexed at a height
+#[derive(Debug, Serialize)]
+pub struct HeightDiff {
+    pub height: u64,
+    pub only_here: Vec<Txid>,
+    pub only_other: Vec<Txid>,
+}
+
+/// Fetches the blocks to index in batches of `rpc_concurrency` on a background thread, so the
+/// next batch is on its way while the current one is scanned. At most one batch waits to be
+/// taken
+struct Prefetcher<'scope, 'env> {
+    scope: &'scope Scope<'scope, 'env>,
+    app: &'env App,
+    source: &'env dyn BlockSource,
+    end: u64,
+    blocks: Receiver<Result<Block>>,
+}
+
+impl<'scope, 'env> Prefetcher<'scope, 'env> {
+    fn start(
+        scope: &'scope Scope<'scope, 'env>,
+        app: &'env App,
+        source: &'env dyn BlockSource,
+        heights: Range<u64>,
+    ) -> Self {
+        Self {
+            scope,
+            app,
+            source,
+            end: heights.end,
+            blocks: Self::spawn(scope, app, source, heights),
+        }
+    }
+
+    /// Fetch from `height` on instead, after a reorg. Blocks fetched for the old chain are dropped
+    fn restart(&mut self, height: u64) {
+        // replacing the receiver stops the old thread at its next send
+        self.blocks = Self::spawn(self.scope, self.app, self.source, height..self.end);
+    }
+
+    /// The block at the next height
+    fn next(&self) -> Result<Block> {
+        self.blocks.recv().expect("block prefetch thread stopped")
+    }
+
+    fn spawn(
+        scope: &'scope Scope<'scope, 'env>,
+        app: &'env App,
+        source: &'env dyn BlockSource,
+        heights: Range<u64>,
+    ) -> Receiver<Result<Block>> {
+        let (sender, receiver) = mpsc::sync_channel(app.rpc_concurrency as usize);
+        scope.spawn(move || {
+            let mut height = heights.start;
+            while height < heights.end {
+                let fetch_till = (height + app.rpc_concurrency).min(heights.end);
+                let blocks = match app.fetch_blocks(source, height..fetch_till) {
+                    Ok(blocks) => blocks,
+                    Err(err) => {
+                        // the indexer stops at the first error, nothing to fetch after it
+                        let _ = sender.send(Err(err));
+                        return;
+                    }
+                };
+                for block in blocks {
+                    if sender.send(Ok(block)).is_err() {
+                        return;
+                    }
+                }
+                height = fetch_till;
+            }
+        });
+        receiver
+    }
+}
+
+/// What `App::diff` reads from the db compared against. Opening it writes nothing, unlike
+/// `App::new` which stores the start block and migrates the checkpoint
+pub struct IndexView {
+    start_block: u64,
+    checkpoint: u64,
+    hashes: Tree,
+    heights: Tree,
+}
+
+impl IndexView {
+    /// Open the index of `opcodes` in the db at `db_path`, which has to exist already. A db
+    /// without a stored start block is assumed to start at the default of `network`
+    pub fn open(db_path: &Path, opcodes: &[Opcode], network: Network) -> Result<Self> {
+        let db = sled::open(db_path).map_err(|source| FelixError::OpenDb {
+            path: db_path.to_path_buf(),
+            source,
+        })?;
+        let namespace = namespace(opcodes);
+        let tree_names = db.tree_names();
+        let open_tree = |name: &str| {
+            let name = match &namespace {
+                Some(namespace) => format!("{}/{}", namespace, name),
+                None => name.to_string(),
+            };
+            // open_tree would create a missing tree
+            if !tree_names.contains(&name.as_str().into()) {
+                return Err(FelixError::NoIndex {
+                    path: db_path.to_path_buf(),
+                    opcodes: namespace.clone().unwrap_or_else(|| opcode_name(OP_CAT)),
+                });
+            }
+            Ok(db.open_tree(name)?)
+        };
+        let meta = match &namespace {
+            Some(_) => open_tree(META_TREE)?,
+            None => Tree::clone(&db),
+        };
+        let hashes = open_tree(HASHES_TREE)?;
+        let heights = open_tree(HEIGHTS_TREE)?;
+
+        let start_block = match meta.get(START_BLOCK_SLED_KEY)? {
+            Some(bytes) => ciborium::from_reader(bytes.as_ref())?,
+            None => network.default_start_block(),
+        };
+        let checkpoint = match meta.get(CHECKPOINT_SLED_KEY)? {
+            Some(bytes) => {
+                let checkpoint: u64 = ciborium::from_reader(bytes.as_ref())?;
+                // the last indexed height rather than the next one, see `migrate_check_point`
+                match meta.get(CHECKPOINT_VERSION_SLED_KEY)? {
+                    Some(_) => checkpoint,
+                    None => checkpoint + 1,
+                }
+            }
+            None => start_block,
+        };
+
+        Ok(Self {
+            start_block,
+            checkpoint,
+            hashes,
+            heights,
+        })
+    }
+
+    /// See `App::indexed_end`
+    pub fn indexed_end(&self) -> Result<u64> {
+        indexed_end(self.checkpoint, &self.hashes)
+    }
+}
+
+/// An indexed tx with inputs matching a script query, see `App::find_script`
+#[derive(Debug, Serialize)]
+pub struct ScriptHit {
+    pub height: u64,
+    pub txid: Txid,
+    /// indices of the inputs whose script matched
+    pub inputs: Vec<u32>,
+}
+
+/// A script checked by the detector, see `detect_witness` and `detect_script`
+#[derive(Debug, Serialize)]
+pub struct Detection {
+    /// index of the script among the witness elements, None for a tapscript given on its own
+    pub witness_index: Option<usize>,
+    pub hex: String,
+    pub asm: String,
+    /// the indexed opcodes the script executes, empty when it uses none of them
+    pub opcodes: Vec<String>,
+}
+
+/// Size and contents of the db, see `App::db_info`
+#[derive(Debug, Serialize)]
+pub struct DbInfo {
+    /// bytes sled takes up on disk, for every opcode set indexed in the db
+    pub size_on_disk: u64,
+    pub heights: u64,
+    pub txs: u64,
+    /// None when nothing has been indexed yet
+    pub checkpoint: Option<u64>,
+    pub start_block: u64,
+    /// entries and key plus value bytes of every tree of the index, by tree name
+    pub trees: BTreeMap<String, TreeInfo>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TreeInfo {
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+/// A txid indexed under more than one height, see `App::check_duplicates`
+#[derive(Debug, Serialize)]
+pub struct DuplicateTxid {
+    pub txid: Txid,
+    pub heights: Vec<u64>,
+}
+
+/// How far indexing trails the node, see `App::lag`
+#[derive(Debug, Serialize)]
+pub struct Lag {
+    pub tip: u64,
+    /// tip - confirmations, the height indexing stops before
+    pub target: u64,
+    pub checkpoint: u64,
+    /// blocks between the checkpoint and the target
+    pub behind: u64,
+}
+
+/// Self describing copy of the index that doesn't depend on the sled on disk format
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    version: u64,
+    checkpoint: Option<u64>,
+    blocks: Vec<SnapshotBlock>,
+}
+
+/// Just the version of a snapshot, whatever the rest of it looks like
+#[derive(Debug, Deserialize)]
+struct SnapshotVersion {
+    version: u64,
+}
+
+/// Everything stored for one indexed height
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotBlock {
+    height: u64,
+    hash: Option<BlockHash>,
+    block_time: Option<u32>,
+    txs: Vec<TransactionExt>,
+}
+
+/// The indexer, and the queries over what it indexed
+pub struct App {
+    /// None for db only commands, which run without bitcoind
+    bitcoind_rpc: Option<FailoverRpc>,
+    network: Network,
+    start_block: u64,
+    confirmations: u64,
+    rpc_concurrency: u64,
+    opcodes: Vec<Opcode>,
+    strict_prevout_check: bool,
+    with_annex: bool,
+    scan_all_witness_items: bool,
+    /// Scans the transactions of a block in parallel
+    parse_pool: rayon::ThreadPool,
+    output_dir: PathBuf,
+    compress: bool,
+    /// Previously fetched prevout transactions for --strict-prevout-check
+    prevout_cache: Mutex<LruCache<Txid, Transaction>>,
+    db: Db,
+    /// Checkpoint, totals and the other single keys of the index, the default tree of the db
+    /// unless the opcodes are namespaced
+    meta: Tree,
+    hashes: Tree,
+    block_times: Tree,
+    taproot_spends: Tree,
+    prevouts: Tree,
+    heights: Tree,
+    txs: Tree,
+    first_seen: Tree,
+    /// Set by the ctrl-c handler, indexing stops once the current block is persisted
+    shutdown: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+}
+
+impl App {
+    pub fn new(config: Config) -> Result<Self> {
+        let bitcoind_rpc = if config.offline {
+            None
+        } else {
+            Some(Self::connect(&config)?)
+        };
+        let db_path = &config.db_path;
+        let db = if db_path.as_os_str() == IN_MEMORY_DB_PATH {
+            info!("opening temporary in memory db");
+            sled::Config::new().temporary(true).open()
+        } else {
+            info!("opening db at: {}", db_path.display());
+            sled::open(db_path)
+        }
+        .map_err(|source| FelixError::OpenDb {
+            path: db_path.clone(),
+            source,
+        })?;
+        // Every opcode set gets trees of its own, OP_CAT alone keeps the unprefixed ones so dbs
+        // from before namespacing open unchanged
+        let namespace = namespace(&config.opcodes);
+        let open_tree = |name: &str| match &namespace {
+            Some(namespace) => db.open_tree(format!("{}/{}", namespace, name)),
+            None => db.open_tree(name),
+        };
+        let meta = match &namespace {
+            Some(_) => open_tree(META_TREE)?,
+            None => Tree::clone(&db),
+        };
+        if let Some(namespace) = &namespace {
+            info!("using the index of opcodes {}", namespace);
+        }
+        let hashes = open_tree(HASHES_TREE)?;
+        let block_times = open_tree(BLOCK_TIMES_TREE)?;
+        let taproot_spends = open_tree(TAPROOT_SPENDS_TREE)?;
+        let prevouts = open_tree(PREVOUTS_TREE)?;
+        let heights = open_tree(HEIGHTS_TREE)?;
+        let txs = open_tree(TXS_TREE)?;
+        let first_seen = open_tree(FIRST_SEEN_TREE)?;
+        let parse_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(config.parse_threads)
+            .build()?;
+        let mut config = config;
+        if let Some(hash) = config.start_block_hash {
+            let rpc = bitcoind_rpc.as_ref().ok_or(FelixError::Offline)?;
+            config.start_block = Some(Self::active_block_height(rpc, &hash)?);
+        }
+        let start_block = Self::resolve_start_block(&meta, &config)?;
+        let app = Self {
+            bitcoind_rpc,
+            network: config.network,
+            start_block,
+            confirmations: config.confirmations,
+            rpc_concurrency: config.rpc_concurrency.max(1),
+            opcodes: config.opcodes,
+            strict_prevout_check: config.strict_prevout_check,
+            with_annex: config.with_annex,
+            scan_all_witness_items: config.scan_all_witness_items,
+            parse_pool,
+            output_dir: config.output_dir,
+            compress: config.compress,
+            prevout_cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(PREVOUT_CACHE_SIZE).expect("non zero cache size"),
+            )),
+            db,
+            meta,
+            hashes,
+            block_times,
+            taproot_spends,
+            prevouts,
+            heights,
+            txs,
+            first_seen,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            metrics: Arc::new(Metrics::default()),
+        };
+        app.migrate_check_point()?;
+        app.backfill_first_seen()?;
+        if !app.legacy_heights()?.is_empty() {
+            warn!("db uses the old per height storage layout, run `migrate` to convert it");
+        }
+        Ok(app)
+    }
+
+    /// Connect to bitcoind and make sure it's on the configured network
+    fn connect(config: &Config) -> Result<FailoverRpc> {
+        let url = config.bitcoind_urls.join(",");
+        let connect_error = |source| FelixError::Connect {
+            url: url.clone(),
+            source,
+        };
+        let bitcoind_rpc = FailoverRpc::new(
+            &config.bitcoind_urls,
+            config.bitcoind_auth.clone(),
+            config.rpc_max_retries,
+        )
+        .map_err(connect_error)?;
+        // test the connection, and that we are about to index the chain we were asked to
+        let blockchain_info = bitcoind_rpc
+            .call::<serde_json::Value>("getblockchaininfo", &[])
+            .map_err(connect_error)?;
+        let chain = blockchain_info["chain"].as_str().unwrap_or_default();
+        if chain != config.network.chain_name() {
+            return Err(FelixError::WrongNetwork {
+                url,
+                chain: chain.to_string(),
+                expected: config.network,
+            });
+        }
+
+        Ok(bitcoind_rpc)
+    }
+
+    /// Height of the block `hash`, which has to be on the active chain
+    fn active_block_height(rpc: &FailoverRpc, hash: &BlockHash) -> Result<u64> {
+        let header = rpc.retry(|rpc| rpc.get_block_header_info(hash))?;
+        // blocks off the active chain have -1 confirmations
+        if header.confirmations < 0 {
+            return Err(FelixError::InactiveBlock(*hash));
+        }
+        info!("start block {} is at height {}", hash, header.height);
+        Ok(header.height as u64)
+    }
+
+    /// The start block of a db is stored the first time it's opened, so a different start block
+    /// or default can't silently change the base of an existing index
+    fn resolve_start_block(meta: &Tree, config: &Config) -> Result<u64> {
+        let stored = match meta.get(START_BLOCK_SLED_KEY)? {
+            Some(bytes) => Some(ciborium::from_reader::<u64, _>(bytes.as_ref())?),
+            None => None,
+        };
+        let start_block = match (stored, config.start_block) {
+            (Some(stored), Some(given)) if stored != given && !config.force => {
+                return Err(FelixError::StartBlockMismatch { stored, given });
+            }
+            (_, Some(given)) => given,
+            (Some(stored), None) => stored,
+            (None, None) => config.network.default_start_block(),
+        };
+        if stored != Some(start_block) {
+            let mut bytes = Vec::new();
+            ciborium::into_writer(&start_block, &mut bytes)?;
+            meta.insert(START_BLOCK_SLED_KEY, bytes)?;
+        }
+
+        Ok(start_block)
+    }
+
+    /// Index the blocks of `source`, bitcoind unless indexing from block files
+    pub fn start_index(&self, source: &dyn BlockSource, options: &IndexOptions) -> Result<()> {
+        self.start_index_with(source, options, |_, _| {})
+    }
+
+    /// `start_index`, handing the txs of every block to `on_block` once they are persisted
+    pub fn start_index_with(
+        &self,
+        source: &dyn BlockSource,
+        options: &IndexOptions,
+        mut on_block: impl FnMut(u64, &[TransactionExt]),
+    ) -> Result<()> {
+        if let Some(start_from) = options.start_from {
+            self.check_checkpoint_height(start_from, source.tip()?)?;
+        }
+        if !options.dry_run {
+            self.insert_index_filter(&options.filter)?;
+        }
+        // Once the first pass stored a checkpoint, following resumes from it
+        let mut options = options.clone();
+        let poll_interval = Duration::from_secs(options.poll_interval);
+        // delivers the notifications still queued when dropped on return
+        let webhook = options.webhook.as_deref().map(Webhook::new);
+        loop {
+            if !self.index_to_tip(source, &options, webhook.as_ref(), &mut on_block)?
+                || !options.follow
+                || options.range.is_some()
+            {
+                return Ok(());
+            }
+            options.start_from = None;
+
+            debug!("caught up, polling again in {:?}", poll_interval);
+            let polled_at = Instant::now();
+            while polled_at.elapsed() < poll_interval {
+                if self.shutdown.load(Ordering::SeqCst) {
+                    info!("stopped at height {}", self.retrieve_check_point()?);
+                    return Ok(());
+                }
+                std::thread::sleep(Duration::from_millis(500));
+            }
+        }
+    }
+
+    /// Index up to `tip - confirmations`, or only the given range without touching the checkpoint.
+    /// Returns false if indexing was interrupted by a shutdown
+    fn index_to_tip(
+        &self,
+        source: &dyn BlockSource,
+        options: &IndexOptions,
+        webhook: Option<&Webhook>,
+        on_block: &mut dyn FnMut(u64, &[TransactionExt]),
+    ) -> Result<bool> {
+        let range = options.range.as_ref();
+        // get tip
+        let tip = source.tip()?;
+        let mut index_till = tip.saturating_sub(self.confirmations);
+
+        // get checkpoint
+        let checkpoint = match range {
+            Some(range) => {
+                info!(
+                    "indexing range {}..{}, checkpoint tracking is disabled",
+                    range.start, range.end
+                );
+                index_till = index_till.min(range.end);
+                range.start
+            }
+            None => match options.start_from {
+                Some(start_from) => {
+                    info!(
+                        "starting from {}, ignoring the stored checkpoint",
+                        start_from
+                    );
+                    start_from
+                }
+                None => self.retrieve_check_point()?,
+            },
+        };
+        info!("Current checkpoint height: {}", checkpoint);
+        if let Some(max_blocks) = options.max_blocks {
+            if index_till > checkpoint.saturating_add(max_blocks) {
+                index_till = checkpoint.saturating_add(max_blocks);
+                info!(
+                    "indexing at most {} blocks, up to {}",
+                    max_blocks, index_till
+                );
+            }
+        }
+        if checkpoint < index_till {
+            if let Some(prune_height) = source.prune_height()? {
+                if checkpoint < prune_height {
+                    return Err(FelixError::Pruned {
+                        start: checkpoint,
+                        prune_height,
+                    });
+                }
+            }
+        }
+        self.store_metrics(tip)?;
+
+        let total_blocks = index_till.saturating_sub(checkpoint);
+        let progress = std::io::stderr().is_terminal().then(|| {
+            let progress = ProgressBar::new(total_blocks);
+            progress.set_style(
+                ProgressStyle::with_template(
+                    "{msg} [{bar:40}] {pos}/{len} blocks, {per_sec}, eta {eta}",
+                )
+                .expect("progress template")
+                .progress_chars("=> "),
+            );
+            progress
+        });
+
+        std::thread::scope(|scope| {
+            let mut prefetcher = Prefetcher::start(scope, self, source, checkpoint..index_till);
+            let mut height = checkpoint;
+            let mut sync = SyncSummary {
+                started_at: chrono::Utc::now().timestamp(),
+                start_height: checkpoint,
+                ..Default::default()
+            };
+            let started = Instant::now();
+            while height < index_till {
+                if self.shutdown.load(Ordering::SeqCst) {
+                    self.db.flush()?;
+                    if let Some(progress) = &progress {
+                        progress.abandon();
+                    }
+                    info!("stopped at height {}", height);
+                    self.finish_sync(sync, height, started, options.dry_run)?;
+                    return Ok(false);
+                }
+
+                // Make sure the block we are building on is still part of the active chain
+                if height > self.start_block {
+                    if let Some(fork_height) = self.detect_reorg(source, height - 1)? {
+                        // A range can't be rolled back past its start
+                        if range.is_some_and(|range| fork_height < range.start) {
+                            return Err(FelixError::Reorg { at: fork_height });
+                        }
+                        warn!("reorg detected, rolling back to height: {}", fork_height);
+                        self.rollback(fork_height, range.is_none())?;
+                        height = fork_height;
+                        prefetcher.restart(height);
+                        continue;
+                    }
+                }
+
+                let block = prefetcher.next()?;
+                let mut tx_exts = self.scan_block(height, &block)?;
+                sync.candidates += tx_exts.len() as u64;
+                tx_exts.retain(|tx_ext| options.filter.keeps(&tx_ext.tx));
+                sync.cat_txs += tx_exts.len() as u64;
+                let taproot_spends = options.index_all_taproot.then(|| TaprootSpends::of(&block));
+                if options.dry_run {
+                    if !tx_exts.is_empty() {
+                        info!("block height: {}, cat txs: {}", height, tx_exts.len());
+                    }
+                    if let Some(spends) = &taproot_spends {
+                        debug!(
+                            "block height: {}, key path spends: {}, script path spends: {}",
+                            height, spends.key_path, spends.script_path
+                        );
+                    }
+                } else {
+                    if let Some(spends) = taproot_spends {
+                        self.insert_taproot_spends(height, spends)?;
+                    }
+                    let txids = self.insert_block(height, &block, &mut tx_exts, options)?;
+                    if let Some(webhook) = webhook.filter(|_| !txids.is_empty()) {
+                        webhook.notify(height, &txids);
+                    }
+                    on_block(height, &tx_exts);
+                }
+                height += 1;
+                sync.blocks += 1;
+                if range.is_none() && !options.dry_run {
+                    self.store_check_point(height)?;
+                }
+                if sync.blocks.is_multiple_of(options.flush_interval.max(1)) {
+                    self.db.flush()?;
+                }
+                self.metrics
+                    .checkpoint_height
+                    .store(height, Ordering::Relaxed);
+                self.metrics
+                    .total_cat_txs
+                    .store(self.get_total_cat_txs()?, Ordering::Relaxed);
+                self.metrics
+                    .blocks_indexed_total
+                    .fetch_add(1, Ordering::Relaxed);
+
+                let indexed = height.saturating_sub(checkpoint);
+                match &progress {
+                    Some(progress) => {
+                        progress.set_position(indexed);
+                        progress.set_message(format!("height {}/{}", height, index_till));
+                    }
+                    None if indexed % PROGRESS_LOG_INTERVAL == 0 => info!(
+                        "indexed {}/{} blocks ({:.1}%), height {}",
+                        indexed,
+                        total_blocks,
+                        indexed as f64 * 100.0 / total_blocks as f64,
+                        height
+                    ),
+                    None => {}
+                }
+            }
+            self.db.flush()?;
+            if let Some(progress) = &progress {
+                progress.finish();
+            }
+            self.finish_sync(sync, height, started, options.dry_run)?;
+
+            Ok(true)
+        })
+    }
+
+    /// Log how an indexing run went and, unless nothing was written, keep it for `last_sync`
+    fn finish_sync(
+        &self,
+        mut sync: SyncSummary,
+        end_height: u64,
+        started: Instant,
+        dry_run: bool,
+    ) -> Result<()> {
+        sync.finished_at = chrono::Utc::now().timestamp();
+        sync.end_height = end_height;
+        sync.seconds = started.elapsed().as_secs_f64();
+        if sync.seconds > 0.0 {
+            sync.blocks_per_sec = sync.blocks as f64 / sync.seconds;
+        }
+        // Nothing to tell about a poll in follow mode that found no new blocks
+        if sync.blocks == 0 && !dry_run {
+            return Ok(());
+        }
+        info!(
+            "indexed {} blocks ({}..{}) in {:.1} seconds, {:.2} blocks/sec, found {} cat txs",
+            sync.blocks,
+            sync.start_height,
+            sync.end_height,
+            sync.seconds,
+            sync.blocks_per_sec,
+            sync.cat_txs
+        );
+        if sync.candidates != sync.cat_txs {
+            info!(
+                "{} of {} matching txs met the index filter",
+                sync.cat_txs, sync.candidates
+            );
+        }
+        if dry_run {
+            info!("dry run, nothing was written");
+            return Ok(());
+        }
+
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&sync, &mut bytes)?;
+        self.meta.insert(LAST_SYNC_SLED_KEY, bytes)?;
+        self.db.flush()?;
+
+        Ok(())
+    }
+
+    /// Remember the filter txs are indexed with, warning when it changes since the heights
+    /// indexed before were filtered differently
+    fn insert_index_filter(&self, filter: &IndexFilter) -> Result<()> {
+        let stored = self.index_filter()?;
+        if stored == *filter {
+            return Ok(());
+        }
+        if stored.is_active() || !self.heights.is_empty() {
+            warn!(
+                "index filter changed from {:?} to {:?}, heights indexed before keep the old one",
+                stored, filter
+            );
+        }
+        let mut bytes = Vec::new();
+        ciborium::into_writer(filter, &mut bytes)?;
+        self.meta.insert(INDEX_FILTER_SLED_KEY, bytes)?;
+        self.db.flush()?;
+
+        Ok(())
+    }
+
+    /// Filter the index was last built with, the default one keeps every matching tx
+    pub fn index_filter(&self) -> Result<IndexFilter> {
+        match self.meta.get(INDEX_FILTER_SLED_KEY)? {
+            Some(bytes) => Ok(ciborium::from_reader(bytes.as_ref())?),
+            None => Ok(IndexFilter::default()),
+        }
+    }
+
+    /// Summary of the last indexing run that indexed any block
+    pub fn last_sync(&self) -> Result<Option<SyncSummary>> {
+        match self.meta.get(LAST_SYNC_SLED_KEY)? {
+            Some(bytes) => Ok(Some(ciborium::from_reader(bytes.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Flag stopping indexing once the current block is persisted, e.g. from a ctrl-c handler
+    pub fn shutdown_handle(&self) -> Arc<AtomicBool> {
+        self.shutdown.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// The bitcoind connection, or an error when running a db only command
+    pub fn rpc(&self) -> Result<&FailoverRpc> {
+        self.bitcoind_rpc.as_ref().ok_or(FelixError::Offline)
+    }
+
+    /// bitcoind as the source of blocks, for the commands that only need the chain itself
+    pub fn block_source(&self) -> Result<&dyn BlockSource> {
+        Ok(self.rpc()?)
+    }
+
+    /// Height the index starts from
+    pub fn start_block(&self) -> u64 {
+        self.start_block
+    }
+
+    /// Height after the highest indexed one, past the checkpoint if a --range went further
+    pub fn indexed_end(&self) -> Result<u64> {
+        indexed_end(self.retrieve_check_point()?, &self.hashes)
+    }
+
+    /// Height of the node's best block
+    pub fn tip_height(&self) -> Result<u64> {
+        self.block_source()?.tip()
+    }
+
+    pub fn lag(&self) -> Result<Lag> {
+        let tip = self.tip_height()?;
+        let target = tip.saturating_sub(self.confirmations);
+        let checkpoint = self.retrieve_check_point()?;
+        Ok(Lag {
+            tip,
+            target,
+            checkpoint,
+            behind: target.saturating_sub(checkpoint),
+        })
+    }
+
+    /// Read the gauges that aren't tied to this process' progress from the db and bitcoind
+    pub fn refresh_metrics(&self) -> Result<()> {
+        self.store_metrics(self.tip_height()?)
+    }
+
+    /// Set the gauges from the db and the `tip` of the block source
+    fn store_metrics(&self, tip: u64) -> Result<()> {
+        self.metrics.tip_height.store(tip, Ordering::Relaxed);
+        self.metrics
+            .checkpoint_height
+            .store(self.retrieve_check_point()?, Ordering::Relaxed);
+        self.metrics
+            .total_cat_txs
+            .store(self.get_total_cat_txs()?, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    fn fetch_block(&self, source: &dyn BlockSource, height: u64) -> Result<Block> {
+        Ok(source.block_at(height)?.1)
+    }
+
+    /// Fetch the blocks for `heights` in order, with one bitcoind request in flight per block
+    /// when running with an rpc concurrency above 1
+    fn fetch_blocks(&self, source: &dyn BlockSource, heights: Range<u64>) -> Result<Vec<Block>> {
+        if self.rpc_concurrency == 1 {
+            return heights
+                .map(|height| self.fetch_block(source, height))
+                .collect();
+        }
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = heights
+                .map(|height| scope.spawn(move || self.fetch_block(source, height)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("block fetch thread panicked"))
+                .collect()
+        })
+    }
+
+    /// Walk backwards from `height` comparing the stored block hashes against the active chain.
+    /// Returns the lowest height whose stored hash no longer matches, if there is one
+    fn detect_reorg(&self, source: &dyn BlockSource, height: u64) -> Result<Option<u64>> {
+        let mut fork_height = None;
+        let mut height = height;
+        while let Some(stored_hash) = self.retrieve_block_hash(height)? {
+            if stored_hash == source.hash_at(height)? {
+                break;
+            }
+            fork_height = Some(height);
+            if height == self.start_block {
+                break;
+            }
+            height -= 1;
+        }
+
+        Ok(fork_height)
+    }
+
+    /// Delete everything indexed at or above `fork_height` and rewind the checkpoint to it, if
+    /// it's being tracked. An untracked checkpoint is still rewound when it's past the fork, so it
+    /// never claims the heights just dropped are indexed
+    fn rollback(&self, fork_height: u64, track_checkpoint: bool) -> Result<()> {
+        self.remove_heights_from(fork_height)?;
+        if track_checkpoint || self.retrieve_check_point()? > fork_height {
+            self.insert_check_point(fork_height)?;
+        }
+
+        Ok(())
+    }
+
+    /// Drop everything indexed at `from` and above
+    fn remove_heights_from(&self, from: u64) -> Result<()> {
+        for key in self.heights.range(from.to_be_bytes()..).keys() {
+            let height = u64::from_be_bytes(key?.as_ref().try_into()?);
+            debug!("removing block at height: {}", height);
+            self.remove_height(height)?;
+        }
+        for key in self.hashes.range(from.to_be_bytes()..).keys() {
+            let key = key?;
+            self.block_times.remove(&key)?;
+            self.hashes.remove(key)?;
+        }
+        for key in self.taproot_spends.range(from.to_be_bytes()..).keys() {
+            self.taproot_spends.remove(key?)?;
+        }
+        self.db.flush()?;
+
+        Ok(())
+    }
+
+    /// Overwrite the checkpoint without touching the indexed heights, e.g. after editing the db
+    /// by hand
+    pub fn set_check_point(&self, height: u64) -> Result<()> {
+        self.check_checkpoint_height(height, self.tip_height()?)?;
+        self.insert_check_point(height)
+    }
+
+    /// Indexing can only start between the start block and the node's tip
+    fn check_checkpoint_height(&self, height: u64, tip: u64) -> Result<()> {
+        if height < self.start_block || height > tip {
+            return Err(FelixError::CheckpointOutOfRange {
+                height,
+                start_block: self.start_block,
+                tip,
+            });
+        }
+        Ok(())
+    }
+
+    /// Drop every height from `from` on and continue indexing there
+    pub fn reset_from(&self, from: u64) -> Result<()> {
+        self.remove_heights_from(from)?;
+        self.insert_check_point(from)
+    }
+
+    /// Drop the whole index, including the checkpoint
+    pub fn reset_all(&self) -> Result<()> {
+        for tree in [
+            &self.hashes,
+            &self.block_times,
+            &self.taproot_spends,
+            &self.prevouts,
+            &self.heights,
+            &self.txs,
+            &self.first_seen,
+        ] {
+            tree.clear()?;
+        }
+        self.meta.clear()?;
+        // an empty db is already on the current checkpoint version
+        self.migrate_check_point()
+    }
+
+    /// Rewrite the checkpoint to the end of the heights indexed without a gap from the start
+    /// block, as told by their stored block hashes
+    pub fn repair_check_point(&self) -> Result<CheckpointRepair> {
+        let previous = match self.meta.get(CHECKPOINT_SLED_KEY)? {
+            Some(bytes) => Some(ciborium::from_reader(bytes.as_ref())?),
+            None => None,
+        };
+        let mut gaps = vec![];
+        let mut next = self.start_block;
+        for key in self.hashes.range(self.start_block.to_be_bytes()..).keys() {
+            let height = u64::from_be_bytes(key?.as_ref().try_into()?);
+            if height > next {
+                gaps.push(HeightGap {
+                    start: next,
+                    end: height,
+                });
+            }
+            next = height + 1;
+        }
+        let checkpoint = gaps.first().map_or(next, |gap| gap.start);
+        for gap in gaps.iter() {
+            warn!("heights {}..{} are not indexed", gap.start, gap.end);
+        }
+        self.insert_check_point(checkpoint)?;
+
+        Ok(CheckpointRepair {
+            previous,
+            checkpoint,
+            gaps,
+        })
+    }
+
+    pub fn db_info(&self) -> Result<DbInfo> {
+        let mut trees = BTreeMap::new();
+        for (name, tree) in [
+            (META_TREE, &self.meta),
+            (HASHES_TREE, &self.hashes),
+            (BLOCK_TIMES_TREE, &self.block_times),
+            (TAPROOT_SPENDS_TREE, &self.taproot_spends),
+            (PREVOUTS_TREE, &self.prevouts),
+            (HEIGHTS_TREE, &self.heights),
+            (TXS_TREE, &self.txs),
+            (FIRST_SEEN_TREE, &self.first_seen),
+        ] {
+            let mut info = TreeInfo::default();
+            for entry in tree.iter() {
+                let (key, value) = entry?;
+                info.entries += 1;
+                info.bytes += (key.len() + value.len()) as u64;
+            }
+            trees.insert(name.to_string(), info);
+        }
+
+        Ok(DbInfo {
+            size_on_disk: self.db.size_on_disk()?,
+            heights: self.heights.len() as u64,
+            txs: self.txs.len() as u64,
+            checkpoint: match self.meta.get(CHECKPOINT_SLED_KEY)? {
+                Some(bytes) => Some(ciborium::from_reader(bytes.as_ref())?),
+                None => None,
+            },
+            start_block: self.start_block,
+            trees,
+        })
+    }
+
+    /// Txids listed under more than one indexed height, which only a mishandled reorg would
+    /// leave behind
+    pub fn check_duplicates(&self) -> Result<Vec<DuplicateTxid>> {
+        let mut heights_by_txid: BTreeMap<Txid, Vec<u64>> = BTreeMap::new();
+        for entry in self.heights.iter() {
+            let (key, value) = entry?;
+            let height = u64::from_be_bytes(key.as_ref().try_into()?);
+            for txid in ciborium::from_reader::<Vec<Txid>, _>(value.as_ref())? {
+                heights_by_txid.entry(txid).or_default().push(height);
+            }
+        }
+
+        Ok(heights_by_txid
+            .into_iter()
+            .filter(|(_, heights)| heights.len() > 1)
+            .map(|(txid, heights)| DuplicateTxid { txid, heights })
+            .collect())
+    }
+
+    /// Check every indexed height still corresponds to the node's active chain
+    pub fn verify(&self) -> Result<VerifySummary> {
+        if self.meta.get(CHECKPOINT_SLED_KEY)?.is_none() {
+            return Err(FelixError::CheckpointMissing);
+        }
+        let mut summary = VerifySummary::default();
+        let checkpoint = self.retrieve_check_point()?;
+        for height in self.start_block..checkpoint {
+            match self.retrieve_block_hash(height)? {
+                Some(stored_hash) => {
+                    let hash = self.block_source()?.hash_at(height)?;
+                    if stored_hash == hash {
+                        summary.matched += 1;
+                    } else {
+                        warn!(
+                            "height {} diverged, stored: {}, node: {}",
+                            height, stored_hash, hash
+                        );
+                        summary.mismatched.push(height);
+                    }
+                }
+                None => summary.missing += 1,
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Compare the txids indexed at every height both this db and `other` cover, to catch
+    /// detector nondeterminism or nodes disagreeing between two independent syncs
+    pub fn diff(&self, other: &IndexView) -> Result<DbDiff> {
+        let start = self.start_block.max(other.start_block);
+        let end = self.indexed_end()?.min(other.indexed_end()?);
+        let mut heights = BTreeSet::new();
+        for tree in [&self.heights, &other.heights] {
+            for key in tree
+                .range(start.to_be_bytes()..end.max(start).to_be_bytes())
+                .keys()
+            {
+                heights.insert(u64::from_be_bytes(key?.as_ref().try_into()?));
+            }
+        }
+
+        let mut diff = DbDiff {
+            start,
+            end,
+            differing: vec![],
+            only_here: 0,
+            only_other: 0,
+            details: vec![],
+        };
+        for height in heights {
+            let here: BTreeSet<Txid> = self.retrieve_block_txids(height)?.into_iter().collect();
+            let there: BTreeSet<Txid> = block_txids(&other.heights, height)?.into_iter().collect();
+            if here == there {
+                continue;
+            }
+            let only_here: Vec<Txid> = here.difference(&there).copied().collect();
+            let only_other: Vec<Txid> = there.difference(&here).copied().collect();
+            diff.differing.push(height);
+            diff.only_here += only_here.len() as u64;
+            diff.only_other += only_other.len() as u64;
+            diff.details.push(HeightDiff {
+                height,
+                only_here,
+                only_other,
+            });
+        }
+
+        Ok(diff)
+    }
+
+    fn insert_block_hash(&self, height: u64, hash: BlockHash) -> Result<()> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&hash, &mut bytes)?;
+        self.hashes.insert(height.to_be_bytes(), bytes)?;
+
+        Ok(())
+    }
+
+    fn insert_block_time(&self, height: u64, time: u32) -> Result<()> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&time, &mut bytes)?;
+        self.block_times.insert(height.to_be_bytes(), bytes)?;
+
+        Ok(())
+    }
+
+    fn insert_taproot_spends(&self, height: u64, spends: TaprootSpends) -> Result<()> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&spends, &mut bytes)?;
+        self.taproot_spends.insert(height.to_be_bytes(), bytes)?;
+
+        Ok(())
+    }
+
+    /// Taproot spends of the heights in `start..finish` that were indexed with
+    /// --index-all-taproot
+    pub fn get_taproot_spends_in_range(
+        &self,
+        start: u64,
+        finish: u64,
+    ) -> Result<Vec<(u64, TaprootSpends)>> {
+        let mut spends = vec![];
+        for entry in self
+            .taproot_spends
+            .range(start.to_be_bytes()..finish.max(start).to_be_bytes())
+        {
+            let (key, value) = entry?;
+            let height = u64::from_be_bytes(key.as_ref().try_into()?);
+            spends.push((height, ciborium::from_reader(value.as_ref())?));
+        }
+        Ok(spends)
+    }
+
+    /// Header timestamp of the block at `height`, fetched from bitcoind and cached if the block
+    /// was indexed before timestamps were stored
+    fn block_time(&self, height: u64) -> Result<u32> {
+        if let Some(bytes) = self.block_times.get(height.to_be_bytes())? {
+            return Ok(ciborium::from_reader(bytes.as_ref())?);
+        }
+
+        let time = self.block_source()?.header_at(height)?.time;
+        self.insert_block_time(height, time)?;
+        Ok(time)
+    }
+
+    fn retrieve_block_hash(&self, height: u64) -> Result<Option<BlockHash>> {
+        match self.hashes.get(height.to_be_bytes())? {
+            Some(bytes) => Ok(Some(ciborium::from_reader(bytes.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn insert_check_point(&self, height: u64) -> Result<()> {
+        self.store_check_point(height)?;
+        self.db.flush()?;
+
+        Ok(())
+    }
+
+    /// Write the checkpoint without flushing, indexing flushes it along with the blocks it covers
+    fn store_check_point(&self, height: u64) -> Result<()> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&height, &mut bytes)?;
+        self.meta.insert(CHECKPOINT_SLED_KEY, bytes)?;
+
+        Ok(())
+    }
+
+    /// Older DBs stored the last indexed height as the checkpoint, bump those by one so the
+    /// checkpoint is the next height to index
+    fn migrate_check_point(&self) -> Result<()> {
+        if self.meta.get(CHECKPOINT_VERSION_SLED_KEY)?.is_some() {
+            return Ok(());
+        }
+
+        if let Some(checkpoint) = self.meta.get(CHECKPOINT_SLED_KEY)? {
+            let height = ciborium::from_reader::<u64, _>(checkpoint.as_ref())?;
+            info!("migrating checkpoint {} to next height to index", height);
+            self.insert_check_point(height + 1)?;
+        }
+
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&CHECKPOINT_VERSION, &mut bytes)?;
+        self.meta.insert(CHECKPOINT_VERSION_SLED_KEY, bytes)?;
+        self.db.flush()?;
+
+        Ok(())
+    }
+
+    pub fn retrieve_check_point(&self) -> Result<u64> {
+        match self.meta.get(CHECKPOINT_SLED_KEY)? {
+            Some(checkpoint) => Ok(ciborium::from_reader(checkpoint.as_ref())?),
+            // if no checkpoint, start from start_block
+            None => Ok(self.start_block),
+        }
+    }
+
+    /// Store the matched transactions of the block at `height`
+    fn insert_block_txs(&self, height: u64, tx_exts: &[TransactionExt]) -> Result<()> {
+        if tx_exts.is_empty() {
+            return Ok(());
+        }
+
+        let mut txids = Vec::with_capacity(tx_exts.len());
+        for tx_ext in tx_exts.iter() {
+            let txid = tx_ext.tx.compute_txid();
+            self.txs
+                .insert(txid.to_string(), self.encode_tx_ext(tx_ext)?)?;
+            txids.push(txid);
+            for input in tx_ext.inputs.iter() {
+                self.insert_first_seen(&input.hex, height)?;
+            }
+        }
+
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&txids, &mut bytes)?;
+        self.heights.insert(height.to_be_bytes(), bytes)?;
+        self.insert_total_cat_txs(self.get_total_cat_txs()? + tx_exts.len() as u64)?;
+
+        Ok(())
+    }
+
+    fn retrieve_block_txids(&self, height: u64) -> Result<Vec<Txid>> {
+        block_txids(&self.heights, height)
+    }
+
+    /// Every indexed tx with its height, by height then in block order. Only the txids of the
+    /// current height are held, each tx is read and decoded as the iterator gets to it
+    pub fn iter_cat_txs(&self) -> impl Iterator<Item = Result<(u64, TransactionExt)>> + '_ {
+        self.iter_cat_txs_in(0..u64::MAX)
+    }
+
+    /// `iter_cat_txs` over the heights in `heights`
+    fn iter_cat_txs_in(
+        &self,
+        heights: Range<u64>,
+    ) -> impl Iterator<Item = Result<(u64, TransactionExt)>> + '_ {
+        self.heights
+            .range(heights.start.to_be_bytes()..heights.end.to_be_bytes())
+            .flat_map(|entry| {
+                let txids = entry.map_err(FelixError::from).and_then(|(key, value)| {
+                    let height = u64::from_be_bytes(key.as_ref().try_into()?);
+                    let txids: Vec<Txid> = ciborium::from_reader(value.as_ref())?;
+                    Ok((height, txids))
+                });
+                match txids {
+                    Ok((height, txids)) => {
+                        txids.into_iter().map(|txid| Ok((height, txid))).collect()
+                    }
+                    Err(err) => vec![Err(err)],
+                }
+            })
+            .filter_map(move |entry| {
+                entry
+                    .and_then(|(height, txid)| {
+                        Ok(self.get_tx(&txid)?.map(|tx_ext| (height, tx_ext)))
+                    })
+                    .transpose()
+            })
+    }
+
+    fn retrieve_block_tx_exts(&self, height: u64) -> Result<Vec<TransactionExt>> {
+        let mut tx_exts = vec![];
+        for txid in self.retrieve_block_txids(height)? {
+            if let Some(tx_ext) = self.get_tx(&txid)? {
+                tx_exts.push(tx_ext);
+            }
+        }
+
+        Ok(tx_exts)
+    }
+
+    /// Remove the transactions indexed at `height` from both the heights and txs trees
+    fn remove_height(&self, height: u64) -> Result<()> {
+        let txids = self.retrieve_block_txids(height)?;
+        if txids.is_empty() {
+            return Ok(());
+        }
+
+        for txid in txids.iter() {
+            if let Some(tx_ext) = self.get_tx(txid)? {
+                for input in tx_ext.inputs.iter() {
+                    if self.retrieve_first_seen(&input.hex)? == Some(height) {
+                        self.first_seen.remove(input.hex.as_bytes())?;
+                    }
+                }
+            }
+            self.txs.remove(txid.to_string())?;
+        }
+        self.heights.remove(height.to_be_bytes())?;
+        self.insert_total_cat_txs(self.get_total_cat_txs()?.saturating_sub(txids.len() as u64))?;
+
+        Ok(())
+    }
+
+    /// Record `height` as where `tapscript_hex` was first spent, unless it's known from a lower one
+    fn insert_first_seen(&self, tapscript_hex: &str, height: u64) -> Result<()> {
+        if self
+            .retrieve_first_seen(tapscript_hex)?
+            .is_some_and(|first_seen| first_seen <= height)
+        {
+            return Ok(());
+        }
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&height, &mut bytes)?;
+        self.first_seen.insert(tapscript_hex.as_bytes(), bytes)?;
+
+        Ok(())
+    }
+
+    fn retrieve_first_seen(&self, tapscript_hex: &str) -> Result<Option<u64>> {
+        match self.first_seen.get(tapscript_hex.as_bytes())? {
+            Some(bytes) => Ok(Some(ciborium::from_reader(bytes.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// DBs indexed before first seen heights were tracked get them from the txs already stored
+    fn backfill_first_seen(&self) -> Result<()> {
+        if !self.first_seen.is_empty() || self.heights.is_empty() {
+            return Ok(());
+        }
+
+        info!("recording the first seen height of every indexed tapscript");
+        for entry in self.iter_cat_txs() {
+            let (height, tx_ext) = entry?;
+            for input in tx_ext.inputs.iter() {
+                self.insert_first_seen(&input.hex, height)?;
+            }
+        }
+        self.db.flush()?;
+
+        Ok(())
+    }
+
+    /// Tapscripts first spent in `heights`, by first seen height and then hex
+    pub fn novel_scripts(&self, heights: Range<u64>) -> Result<Vec<NovelScript>> {
+        let mut scripts = vec![];
+        for entry in self.first_seen.iter() {
+            let (key, value) = entry?;
+            let height = ciborium::from_reader::<u64, _>(value.as_ref())?;
+            if heights.contains(&height) {
+                scripts.push(NovelScript {
+                    height,
+                    tapscript_hex: String::from_utf8_lossy(&key).into_owned(),
+                });
+            }
+        }
+        scripts.sort_by(|a, b| (a.height, &a.tapscript_hex).cmp(&(b.height, &b.tapscript_hex)));
+
+        Ok(scripts)
+    }
+
+    /// Indexed txs spending a script that matches `query`, by height then txid. An exact match
+    /// only scans from the height the script was first seen at
+    pub fn find_script(&self, query: &str, mode: MatchMode) -> Result<Vec<ScriptHit>> {
+        let invalid = || FelixError::ScriptQuery(query.to_string());
+        let (start, pattern) = match mode {
+            MatchMode::Exact => {
+                let hex = query.trim().to_lowercase();
+                hex::decode(&hex).map_err(|_| invalid())?;
+                match self.retrieve_first_seen(&hex)? {
+                    Some(first_seen) => (first_seen, ScriptPattern::Hex(hex)),
+                    None => return Ok(vec![]),
+                }
+            }
+            _ => {
+                let opcodes = query
+                    .split_whitespace()
+                    .map(parse_opcode)
+                    .collect::<Result<Vec<_>>>()?;
+                if opcodes.is_empty() {
+                    return Err(invalid());
+                }
+                (self.start_block, ScriptPattern::Opcodes(mode, opcodes))
+            }
+        };
+
+        let mut hits = vec![];
+        for entry in self.iter_cat_txs_in(start..self.indexed_end()?) {
+            let (height, tx_ext) = entry?;
+            let inputs: Vec<u32> = tx_ext
+                .inputs
+                .iter()
+                .filter(|input| pattern.matches(&input.hex))
+                .map(|input| input.input)
+                .collect();
+            if !inputs.is_empty() {
+                hits.push(ScriptHit {
+                    height,
+                    txid: tx_ext.tx.compute_txid(),
+                    inputs,
+                });
+            }
+        }
+        hits.sort_by_key(|hit| (hit.height, hit.txid));
+
+        Ok(hits)
+    }
+
+    fn insert_total_cat_txs(&self, total: u64) -> Result<()> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&total, &mut bytes)?;
+        self.meta.insert(TOTAL_CAT_TXS_SLED_KEY, bytes)?;
+
+        Ok(())
+    }
+
+    /// Heights stored in the legacy layout, where every height with cat txs was a decimal key in
+    /// the default tree holding a CBOR `HashSet<Transaction>`
+    fn legacy_heights(&self) -> Result<Vec<u64>> {
+        let mut heights = vec![];
+        for key in self.meta.iter().keys() {
+            let key = key?;
+            if let Some(height) = std::str::from_utf8(&key)
+                .ok()
+                .and_then(|key| key.parse::<u64>().ok())
+            {
+                heights.push(height);
+            }
+        }
+
+        Ok(heights)
+    }
+
+    /// Move every height in the legacy layout into the heights and txs trees. Metadata already
+    /// stored for a transaction is kept, otherwise it's derived from the stored transaction
+    pub fn migrate_storage(&self) -> Result<u64> {
+        let heights = self.legacy_heights()?;
+        for height in heights.iter() {
+            let Some(txs) = self.meta.get(height.to_string())? else {
+                continue;
+            };
+            // The legacy set has no order, store its txs by txid to keep the result deterministic
+            let mut set: Vec<Transaction> =
+                ciborium::from_reader::<HashSet<Transaction>, _>(txs.as_ref())?
+                    .into_iter()
+                    .collect();
+            set.sort_by_cached_key(Transaction::compute_txid);
+            let mut tx_exts = vec![];
+            for tx in set.iter() {
+                if let Some(tx_ext) = self.get_tx(&tx.compute_txid())? {
+                    tx_exts.push(tx_ext);
+                    continue;
+                }
+                let spends = tx
+                    .input
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, input)| {
+                        Some((index as u32, witness::parse(&input.witness)?.into()))
+                    })
+                    .collect::<Vec<_>>();
+                tx_exts.push(build_tx_ext(
+                    *height,
+                    self.block_time(*height)?,
+                    tx,
+                    &spends,
+                    &self.opcodes,
+                ));
+            }
+
+            self.remove_height(*height)?;
+            self.insert_block_txs(*height, &tx_exts)?;
+            self.meta.remove(height.to_string())?;
+            debug!("migrated height: {}", height);
+        }
+        self.db.flush()?;
+
+        Ok(heights.len() as u64)
+    }
+
+    /// Write every indexed height and the checkpoint to a single CBOR file at `path`. Returns
+    /// the number of heights exported
+    pub fn export_snapshot(&self, path: &Path) -> Result<u64> {
+        let mut heights = BTreeSet::new();
+        for key in self.hashes.iter().keys().chain(self.heights.iter().keys()) {
+            heights.insert(u64::from_be_bytes(key?.as_ref().try_into()?));
+        }
+
+        let mut blocks = Vec::with_capacity(heights.len());
+        for height in heights {
+            let block_time = match self.block_times.get(height.to_be_bytes())? {
+                Some(bytes) => Some(ciborium::from_reader(bytes.as_ref())?),
+                None => None,
+            };
+            blocks.push(SnapshotBlock {
+                height,
+                hash: self.retrieve_block_hash(height)?,
+                block_time,
+                txs: self.retrieve_block_tx_exts(height)?,
+            });
+        }
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            checkpoint: match self.meta.get(CHECKPOINT_SLED_KEY)? {
+                Some(bytes) => Some(ciborium::from_reader(bytes.as_ref())?),
+                None => None,
+            },
+            blocks,
+        };
+
+        let mut writer = BufWriter::new(std::fs::File::create(path)?);
+        ciborium::into_writer(&snapshot, &mut writer)?;
+        writer.flush()?;
+
+        Ok(snapshot.blocks.len() as u64)
+    }
+
+    /// Load a file written by `export_snapshot` into this db, which has to be empty. Returns the
+    /// number of heights imported
+    pub fn import_snapshot(&self, path: &Path) -> Result<u64> {
+        if !self.hashes.is_empty()
+            || !self.heights.is_empty()
+            || self.meta.get(CHECKPOINT_SLED_KEY)?.is_some()
+        {
+            return Err(FelixError::DbNotEmpty);
+        }
+        let bytes = std::fs::read(path)?;
+        // Check the version on its own first, other versions may not parse as a Snapshot at all
+        let version: SnapshotVersion = ciborium::from_reader(bytes.as_slice())?;
+        if version.version != SNAPSHOT_VERSION {
+            return Err(FelixError::SnapshotVersion {
+                found: version.version,
+                supported: SNAPSHOT_VERSION,
+            });
+        }
+        let snapshot: Snapshot = ciborium::from_reader(bytes.as_slice())?;
+
+        for block in snapshot.blocks.iter() {
+            self.insert_block_txs(block.height, &block.txs)?;
+            if let Some(hash) = block.hash {
+                self.insert_block_hash(block.height, hash)?;
+            }
+            if let Some(time) = block.block_time {
+                self.insert_block_time(block.height, time)?;
+            }
+        }
+        if let Some(checkpoint) = snapshot.checkpoint {
+            self.insert_check_point(checkpoint)?;
+        }
+        self.db.flush()?;
+
+        Ok(snapshot.blocks.len() as u64)
+    }
+
+    pub fn get_tx(&self, txid: &Txid) -> Result<Option<TransactionExt>> {
+        match self.txs.get(txid.to_string())? {
+            Some(bytes) => Ok(Some(decode_tx_ext(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The indexed txs whose txid starts with the hex `prefix`, in txid order. With `unique` a
+    /// prefix matching more than one tx is an error
+    pub fn find_txs(&self, prefix: &str, unique: bool) -> Result<Vec<TransactionExt>> {
+        let prefix = prefix.trim().to_lowercase();
+        if prefix.len() < MIN_TXID_PREFIX_LEN
+            || prefix.len() > 64
+            || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(FelixError::TxidPrefix(prefix));
+        }
+
+        let mut tx_exts = vec![];
+        for bytes in self.txs.scan_prefix(prefix.as_bytes()).values() {
+            tx_exts.push(decode_tx_ext(&bytes?)?);
+        }
+        if unique && tx_exts.len() > 1 {
+            return Err(FelixError::AmbiguousTxid {
+                prefix,
+                matches: tx_exts.len(),
+            });
+        }
+
+        Ok(tx_exts)
+    }
+
+    /// CBOR of `tx_ext` as stored in the txs tree, behind COMPRESSED_MAGIC with --compress
+    fn encode_tx_ext(&self, tx_ext: &TransactionExt) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(tx_ext, &mut bytes)?;
+        if !self.compress {
+            return Ok(bytes);
+        }
+
+        let mut compressed = vec![COMPRESSED_MAGIC];
+        compressed.extend(zstd::encode_all(bytes.as_slice(), 0)?);
+        Ok(compressed)
+    }
+
+    /// Double check with bitcoind that the output `input` spends is a P2TR. A prevout bitcoind
+    /// can't find, e.g. without -txindex, is left to the witness structure alone
+    fn prevout_is_p2tr(&self, input: &TxIn) -> Result<bool> {
+        match self.prevout(&input.previous_output) {
+            Ok(prevout) => Ok(prevout.script_pubkey.is_p2tr()),
+            Err(FelixError::Rpc(err)) if rpc::is_tx_not_found(&err) => {
+                warn!(
+                    "prevout {} not found, bitcoind needs -txindex to check it, trusting the witness",
+                    input.previous_output
+                );
+                Ok(true)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Sum of the prevout values minus the output values. Prevouts come from the db, and from
+    /// bitcoind too when `fetch` is set. None if any prevout is unknown
+    fn tx_fee(&self, tx: &Transaction, fetch: bool) -> Option<u64> {
+        let mut input_value = Amount::ZERO;
+        for input in tx.input.iter() {
+            let prevout = match fetch {
+                true => self.prevout(&input.previous_output),
+                false => self.stored_prevout(&input.previous_output).transpose()?,
+            };
+            match prevout {
+                Ok(prevout) => input_value += prevout.value,
+                // Without bitcoind only the stored prevouts are known
+                Err(FelixError::Offline) => return None,
+                Err(e) => {
+                    warn!(
+                        "fee of {} unknown, failed to fetch prevout {}: {:#}",
+                        tx.compute_txid(),
+                        input.previous_output,
+                        e
+                    );
+                    return None;
+                }
+            }
+        }
+        let output_value = tx.output.iter().map(|output| output.value).sum();
+        input_value
+            .checked_sub(output_value)
+            .map(|fee| fee.to_sat())
+    }
+
+    /// Output spent by an input, from the stored prevouts or through the prevout cache
+    fn prevout(&self, outpoint: &OutPoint) -> Result<TxOut> {
+        if let Some(prevout) = self.stored_prevout(outpoint)? {
+            return Ok(prevout);
+        }
+        let txid = outpoint.txid;
+        let cached = self
+            .prevout_cache
+            .lock()
+            .expect("prevout cache lock")
+            .get(&txid)
+            .cloned();
+        let prevout = match cached {
+            Some(prevout) => prevout,
+            None => {
+                let prevout = self
+                    .rpc()?
+                    .retry(|rpc| rpc.get_raw_transaction(&txid, None))?;
+                self.prevout_cache
+                    .lock()
+                    .expect("prevout cache lock")
+                    .put(txid, prevout.clone());
+                prevout
+            }
+        };
+
+        prevout
+            .output
+            .get(outpoint.vout as usize)
+            .cloned()
+            .ok_or(FelixError::PrevoutMissing(*outpoint))
+    }
+
+    /// Output spent by an input, if it was stored with --store-prevouts
+    fn stored_prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>> {
+        match self.prevouts.get(prevout_key(outpoint))? {
+            Some(bytes) => Ok(Some(ciborium::from_reader(bytes.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the outputs the inputs of `tx` spend. Prevouts that can't be fetched were already
+    /// warned about when working out the fee and are skipped
+    fn insert_prevouts(&self, tx: &Transaction) -> Result<()> {
+        for input in tx.input.iter() {
+            let key = prevout_key(&input.previous_output);
+            if self.prevouts.contains_key(key)? {
+                continue;
+            }
+            if let Ok(prevout) = self.prevout(&input.previous_output) {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(&prevout, &mut bytes)?;
+                self.prevouts.insert(key, bytes)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Describe `tx` if any of its inputs uses the indexed opcodes
+    fn scan_tx(
+        &self,
+        height: u64,
+        block_time: u32,
+        tx: &Transaction,
+    ) -> Result<Option<TransactionExt>> {
+        let mut spends = vec![];
+        for (index, input) in tx.input.iter().enumerate() {
+            // Key path spends, coinbase witnesses and anything else without a tapscript have
+            // nothing to scan, unless every witness element is scanned
+            let scripts = if self.scan_all_witness_items {
+                witness::scripts(&input.witness)
+            } else {
+                witness::parse(&input.witness)
+                    .map(WitnessScript::from)
+                    .into_iter()
+                    .collect()
+            };
+            for script in scripts {
+                if script_matched_opcodes(script.script, &self.opcodes).is_empty() {
+                    continue;
+                }
+                if self.with_annex && script.annex.is_none() {
+                    continue;
+                }
+                // Only tapscripts are expected to spend a P2TR
+                if self.strict_prevout_check
+                    && script.leaf_version.is_some()
+                    && !self.prevout_is_p2tr(input)?
+                {
+                    continue;
+                }
+                spends.push((index as u32, script));
+            }
+        }
+        if spends.is_empty() {
+            return Ok(None);
+        }
+        let tx_ext = build_tx_ext(height, block_time, tx, &spends, &self.opcodes);
+        debug!(
+            "found {} in witness for txid: {}",
+            tx_ext.opcodes.join(","),
+            tx.compute_txid()
+        );
+        Ok(Some(tx_ext))
+    }
+
+    /// Time matching the opcodes against every witness of the blocks in `heights`. The blocks
+    /// are fetched up front and nothing is looked up or written while timing, so this measures
+    /// the witness parsing and opcode iteration alone, on a single thread
+    pub fn bench_detector(&self, heights: Range<u64>) -> Result<BenchSummary> {
+        let mut blocks = vec![];
+        for start in heights.clone().step_by(self.rpc_concurrency as usize) {
+            let end = (start + self.rpc_concurrency).min(heights.end);
+            blocks.extend(self.fetch_blocks(self.block_source()?, start..end)?);
+        }
+        let mut summary = BenchSummary {
+            blocks: blocks.len() as u64,
+            ..Default::default()
+        };
+        let started = Instant::now();
+        for block in blocks.iter() {
+            for tx in block.txdata.iter() {
+                let mut matched = false;
+                for input in tx.input.iter() {
+                    summary.witness_bytes += input.witness.size() as u64;
+                    matched |= if self.scan_all_witness_items {
+                        witness::scripts(&input.witness).iter().any(|script| {
+                            !script_matched_opcodes(script.script, &self.opcodes).is_empty()
+                        })
+                    } else {
+                        witness_uses_opcodes(&input.witness, &self.opcodes)
+                    };
+                }
+                summary.txs += 1;
+                summary.matches += matched as u64;
+            }
+        }
+        summary.seconds = started.elapsed().as_secs_f64();
+        if summary.seconds > 0.0 {
+            summary.txs_per_sec = summary.txs as f64 / summary.seconds;
+            summary.mb_per_sec = summary.witness_bytes as f64 / 1_000_000.0 / summary.seconds;
+        }
+
+        Ok(summary)
+    }
+
+    /// Unconfirmed txs in the node's mempool using the indexed opcodes. Nothing is written to the
+    /// db, so the confirmed index and its stats stay untouched
+    pub fn scan_mempool(&self) -> Result<Vec<MempoolTx>> {
+        let rpc = self.rpc()?;
+        let txids = rpc.retry(|rpc| rpc.get_raw_mempool())?;
+        debug!("scanning {} mempool txs", txids.len());
+        let scan = |txid: &Txid| -> Result<Option<MempoolTx>> {
+            // The tx may have been mined or evicted since the mempool was listed
+            let tx = match rpc.get_raw_transaction(txid, None) {
+                Ok(tx) => tx,
+                Err(err) => {
+                    debug!("skipping mempool tx {}: {}", txid, err);
+                    return Ok(None);
+                }
+            };
+            Ok(self.scan_tx(0, 0, &tx)?.map(|tx_ext| MempoolTx {
+                txid: txid.to_string(),
+                opcodes: tx_ext.opcodes,
+                inputs: tx_ext.inputs,
+                vsize: tx_ext.vsize,
+            }))
+        };
+        let mut matches = self.parse_pool.install(|| {
+            txids
+                .par_iter()
+                .filter_map(|txid| scan(txid).transpose())
+                .collect::<Result<Vec<_>>>()
+        })?;
+        matches.sort_by(|a, b| a.txid.cmp(&b.txid));
+
+        Ok(matches)
+    }
+
+    /// Find the txs of `block` matching the indexed opcodes, without fees and without touching
+    /// the db
+    fn scan_block(&self, height: u64, block: &Block) -> Result<Vec<TransactionExt>> {
+        debug!("parsing block height: {}", height);
+        debug!("total txs in block: {}", block.txdata.len());
+        let scan_tx = |tx: &Transaction| self.scan_tx(height, block.header.time, tx);
+        // Without prevout lookups the scan is pure cpu, so spread it over the pool. Block order
+        // is kept either way
+        if self.strict_prevout_check {
+            block
+                .txdata
+                .iter()
+                .filter_map(|tx| scan_tx(tx).transpose())
+                .collect()
+        } else {
+            self.parse_pool.install(|| {
+                block
+                    .txdata
+                    .par_iter()
+                    .filter_map(|tx| scan_tx(tx).transpose())
+                    .collect()
+            })
+        }
+    }
+
+    /// Store the matching txs `tx_exts` of `block` and return their txids
+    fn insert_block(
+        &self,
+        height: u64,
+        block: &Block,
+        tx_exts: &mut [TransactionExt],
+        options: &IndexOptions,
+    ) -> Result<Vec<Txid>> {
+        // Drop anything left over from a previous pass over this height
+        self.remove_height(height)?;
+        // Storing the prevouts fetches them anyway, otherwise bitcoind is only asked for them on
+        // request since that's a request per spent tx
+        let fetch_prevouts = options.fees || options.store_prevouts;
+        for tx_ext in tx_exts.iter_mut() {
+            tx_ext.fee = self.tx_fee(&tx_ext.tx, fetch_prevouts);
+            if options.store_prevouts {
+                self.insert_prevouts(&tx_ext.tx)?;
+            }
+        }
+        debug!("block height: {}, cat txs: {}", height, tx_exts.len());
+        self.insert_block_txs(height, tx_exts)?;
+        self.insert_block_hash(height, block.block_hash())?;
+        self.insert_block_time(height, block.header.time)?;
+        Ok(tx_exts
+            .iter()
+            .map(|tx_ext| tx_ext.tx.compute_txid())
+            .collect())
+    }
+
+    pub fn get_total_cat_txs(&self) -> Result<u64> {
+        match self.meta.get(TOTAL_CAT_TXS_SLED_KEY)? {
+            Some(bytes) => Ok(ciborium::from_reader(bytes.as_ref())?),
+            None => Ok(0),
+        }
+    }
+
+    /// Return a vector of tuples of block height and total cat txs for that block
+    pub fn get_cats_in_range(&self, start: u64, finish: u64) -> Result<Vec<(u64, u64)>> {
+        let mut total_cats = vec![];
+        for i in start..finish {
+            let txids = self.retrieve_block_txids(i)?;
+            total_cats.push((i, txids.len() as u64));
+        }
+        Ok(total_cats)
+    }
+
+    /// Number of cat txs at every height in `start..finish`, including the heights without any
+    pub fn series(&self, start: u64, finish: u64) -> Result<Vec<BlockCount>> {
+        Ok(self
+            .get_cats_in_range(start, finish)?
+            .into_iter()
+            .map(|(height, count)| BlockCount { height, count })
+            .collect())
+    }
+
+    /// Return a vector of tuples of block height and total OP_CAT opcodes used by that block's cat txs
+    pub fn get_cat_ops_in_range(&self, start: u64, finish: u64) -> Result<Vec<(u64, u64)>> {
+        let mut total_cat_ops = vec![];
+        for i in start..finish {
+            let cat_ops: usize = self
+                .retrieve_block_tx_exts(i)?
+                .iter()
+                .map(|tx_ext| tx_ext.cat_ops)
+                .sum();
+            total_cat_ops.push((i, cat_ops as u64));
+        }
+        Ok(total_cat_ops)
+    }
+
+    /// Return a vector of tuples of block height and the median fee rate in sat/vB of that
+    /// block's cat txs with a known fee, 0 when there are none
+    pub fn get_fee_rates_in_range(&self, start: u64, finish: u64) -> Result<Vec<(u64, u64)>> {
+        let mut fee_rates = vec![];
+        for i in start..finish {
+            let mut rates: Vec<f64> = self
+                .retrieve_block_tx_exts(i)?
+                .iter()
+                .filter_map(TransactionExt::fee_rate)
+                .collect();
+            rates.sort_by(f64::total_cmp);
+            let median = match rates.len() {
+                0 => 0.0,
+                len if len % 2 == 0 => (rates[len / 2 - 1] + rates[len / 2]) / 2.0,
+                len => rates[len / 2],
+            };
+            // float to int casts saturate, they can't wrap
+            fee_rates.push((i, median.round() as u64));
+        }
+        Ok(fee_rates)
+    }
+
+    /// Whether any indexed tx has a known fee, or there's no tx at all
+    fn has_fees(&self) -> Result<bool> {
+        if self.get_total_cat_txs()? == 0 {
+            return Ok(true);
+        }
+        for tx in self.iter_cat_txs() {
+            if tx?.1.fee.is_some() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Median byte length of the tapscripts indexed at every height of a range, 0 for heights
+    /// without any
+    pub fn get_script_sizes_in_range(&self, start: u64, finish: u64) -> Result<Vec<(u64, u64)>> {
+        let mut script_sizes = vec![];
+        for i in start..finish {
+            let mut sizes: Vec<u32> = self
+                .retrieve_block_tx_exts(i)?
+                .iter()
+                .flat_map(|tx_ext| tx_ext.inputs.iter().map(InputTapscript::size))
+                .collect();
+            sizes.sort_unstable();
+            script_sizes.push((i, percentile(&sizes, 50) as u64));
+        }
+        Ok(script_sizes)
+    }
+
+    /// The `limit` indexed heights with the most cat txs, busiest first and lower heights first
+    /// on ties
+    pub fn top_blocks(&self, limit: usize) -> Result<Vec<BlockCount>> {
+        let mut blocks = self.series(self.start_block, self.indexed_end()?)?;
+        blocks.retain(|block| block.count > 0);
+        blocks.sort_by_key(|block| std::cmp::Reverse(block.count));
+        blocks.truncate(limit);
+
+        Ok(blocks)
+    }
+
+    pub fn compute_stats(&self) -> Result<Stats> {
+        let checkpoint = self.retrieve_check_point()?;
+        let mut stats = Stats {
+            start_height: self.start_block,
+            end_height: checkpoint,
+            filter: Some(self.index_filter()?).filter(IndexFilter::is_active),
+            ..Default::default()
+        };
+        let mut annex_bytes = 0;
+        let mut script_sizes = vec![];
+
+        // first height of the current run of blocks without cat txs
+        let mut run_start = None;
+        for (height, count) in self.get_cats_in_range(self.start_block, checkpoint)? {
+            if count == 0 {
+                stats.blocks_without_cats += 1;
+                let start = *run_start.get_or_insert(height);
+                if height + 1 - start > stats.longest_run_without_cats {
+                    stats.longest_run_without_cats = height + 1 - start;
+                    stats.longest_run_without_cats_start = Some(start);
+                }
+                continue;
+            }
+            run_start = None;
+            stats.total_cat_txs += count;
+            stats.blocks_with_cats += 1;
+            if count > stats.busiest_block_cat_txs {
+                stats.busiest_block_height = Some(height);
+                stats.busiest_block_cat_txs = count;
+            }
+        }
+        for entry in self.iter_cat_txs_in(self.start_block..checkpoint) {
+            let (_, tx_ext) = entry?;
+            *stats.cat_ops_histogram.entry(tx_ext.cat_ops).or_default() += 1;
+            for input in tx_ext.inputs.iter() {
+                let leaf_version = match input.leaf_version {
+                    Some(leaf_version) => format!("{:#04x}", leaf_version),
+                    None => "unknown".to_string(),
+                };
+                *stats.leaf_versions.entry(leaf_version).or_default() += 1;
+                script_sizes.push(input.size());
+                if let Some(items) = tx_ext.witness_items(input) {
+                    *stats.witness_items_histogram.entry(items).or_default() += 1;
+                }
+                if let Some(annex) = &input.annex {
+                    stats.annex_spends += 1;
+                    annex_bytes += annex.len() as u64 / 2;
+                }
+            }
+            for output_type in tx_ext.output_types() {
+                *stats.output_types.entry(output_type).or_default() += 1;
+            }
+        }
+        if stats.blocks_with_cats > 0 {
+            stats.avg_cat_txs_per_block_with_cats =
+                stats.total_cat_txs as f64 / stats.blocks_with_cats as f64;
+        }
+        let blocks = checkpoint.saturating_sub(self.start_block);
+        if blocks > 0 {
+            stats.blocks_without_cats_percent =
+                stats.blocks_without_cats as f64 * 100.0 / blocks as f64;
+        }
+        if stats.annex_spends > 0 {
+            stats.avg_annex_size = annex_bytes as f64 / stats.annex_spends as f64;
+        }
+        stats.script_sizes = ScriptSizes::of(script_sizes);
+        let taproot_spends = self.get_taproot_spends_in_range(self.start_block, checkpoint)?;
+        if !taproot_spends.is_empty() {
+            let mut taproot = TaprootStats::default();
+            let mut block_ratios = vec![];
+            for (_, spends) in taproot_spends {
+                taproot.blocks += 1;
+                taproot.key_path_spends += spends.key_path;
+                taproot.script_path_spends += spends.script_path;
+                for (leaf_version, count) in spends.leaf_versions.iter() {
+                    *taproot
+                        .leaf_versions
+                        .entry(format!("{:#04x}", leaf_version))
+                        .or_default() += count;
+                }
+                if spends.key_path > 0 {
+                    block_ratios.push(spends.script_path as f64 / spends.key_path as f64);
+                }
+            }
+            if taproot.key_path_spends > 0 {
+                taproot.script_to_key_path_ratio =
+                    Some(taproot.script_path_spends as f64 / taproot.key_path_spends as f64);
+            }
+            if !block_ratios.is_empty() {
+                taproot.avg_block_ratio =
+                    Some(block_ratios.iter().sum::<f64>() / block_ratios.len() as f64);
+            }
+            stats.taproot = Some(taproot);
+        }
+        for bytes in self.txs.iter().values() {
+            let bytes = bytes?;
+            stats.stored_tx_bytes += bytes.len() as u64;
+            stats.uncompressed_tx_bytes += tx_ext_cbor(&bytes)?.len() as u64;
+        }
+        stats.compression_ratio = if stats.stored_tx_bytes > 0 {
+            stats.uncompressed_tx_bytes as f64 / stats.stored_tx_bytes as f64
+        } else {
+            1.0
+        };
+
+        Ok(stats)
+    }
+
+    /// Heights in `range` with at least one indexed tx, in ascending order
+    fn indexed_heights(&self, range: Range<u64>) -> impl Iterator<Item = Result<u64>> {
+        self.heights
+            .range(range.start.to_be_bytes()..range.end.to_be_bytes())
+            .keys()
+            .map(|key| Ok(u64::from_be_bytes(key?.as_ref().try_into()?)))
+    }
+
+    /// The txs indexed at `height` as they appear in reports, ordered by txid so reports over
+    /// heights in ascending order are sorted by (height, txid) and stable across runs
+    fn report_tx_exts(&self, height: u64) -> Result<Vec<TransactionExt>> {
+        let mut tx_exts = self.retrieve_block_tx_exts(height)?;
+        tx_exts.sort_by_cached_key(|tx_ext| tx_ext.tx.compute_txid());
+        for tx_ext in tx_exts.iter_mut() {
+            // txs indexed before block times were stored
+            if tx_ext.block_time == 0 {
+                tx_ext.block_time = self.block_time(height)?;
+            }
+        }
+
+        Ok(tx_exts)
+    }
+
+    pub fn generate_cat_report(&self, options: &ReportOptions) -> Result<()> {
+        let format = options.format;
+        let checkpoint = self.retrieve_check_point()?;
+
+        if options.append && format != ReportFormat::Jsonl {
+            return Err(FelixError::Unsupported(
+                "only jsonl reports can be appended to",
+            ));
+        }
+        let start_block = match (options.append, self.meta.get(REPORT_END_SLED_KEY)?) {
+            (true, Some(bytes)) => ciborium::from_reader(bytes.as_ref())?,
+            _ => options.report_start.unwrap_or(self.start_block),
+        };
+        let end_block = options.report_end.unwrap_or(checkpoint);
+        if start_block > end_block {
+            return Err(FelixError::ReportRange {
+                start: start_block,
+                end: end_block,
+            });
+        }
+        info!(
+            "generating report for heights {}..{}",
+            start_block, end_block
+        );
+        let filter = self.index_filter()?;
+        if filter.is_active() {
+            warn!(
+                "index is filtered, the report only holds txs meeting {:?}",
+                filter
+            );
+        }
+        let files = if options.group_by_script {
+            self.generate_script_report(format, start_block..end_block)?
+        } else {
+            self.generate_tx_report(options, start_block..end_block)?
+        };
+        self.write_manifest(start_block..end_block, checkpoint, &files)
+    }
+
+    /// Write the txs indexed in `heights` in the report format, returning the files written
+    fn generate_tx_report(
+        &self,
+        options: &ReportOptions,
+        heights: Range<u64>,
+    ) -> Result<Vec<PathBuf>> {
+        let format = options.format;
+        let name = if options.malformed {
+            "malformed_scripts"
+        } else {
+            "cat_txs"
+        };
+        let report_tx_exts = |height| -> Result<Vec<TransactionExt>> {
+            let mut tx_exts = self.report_tx_exts(height)?;
+            tx_exts.retain(|tx_ext| tx_ext.malformed || !options.malformed);
+            Ok(tx_exts)
+        };
+        // One giant vec of TransactionExt for all blocks
+        let mut all_txs = vec![];
+        let mut files = vec![];
+
+        if format == ReportFormat::Jsonl {
+            // Only ever hold a single block's txs in memory
+            let file_name = self.output_path(&format!("{name}.jsonl"))?;
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .append(options.append)
+                .truncate(!options.append)
+                .open(&file_name)?;
+            let mut writer = BufWriter::new(file);
+            for height in self.indexed_heights(heights.clone()) {
+                for tx_ext in report_tx_exts(height?)? {
+                    serde_json::to_writer(&mut writer, &tx_ext)?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+            writer.flush()?;
+            if !options.malformed {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(&heights.end, &mut bytes)?;
+                self.meta.insert(REPORT_END_SLED_KEY, bytes)?;
+                self.db.flush()?;
+            }
+            info!("report generated to {}", file_name.display());
+            return Ok(vec![file_name]);
+        }
+
+        for height in self.indexed_heights(heights.clone()) {
+            all_txs.extend(report_tx_exts(height?)?);
+        }
+
+        if matches!(format, ReportFormat::Json | ReportFormat::Both) {
+            // write to a json file
+            let json = serde_json::to_string(&all_txs)?;
+            let file_name = self.output_path(&format!("{name}.json"))?;
+            std::fs::write(&file_name, json)?;
+            info!("report generated to {}", file_name.display());
+            files.push(file_name);
+        }
+
+        if matches!(format, ReportFormat::Csv | ReportFormat::Both) {
+            let file_name = self.output_path(&format!("{name}.csv"))?;
+            let mut writer = csv::Writer::from_path(&file_name)?;
+            for tx_ext in all_txs.iter() {
+                writer.serialize(CsvRecord::from(tx_ext))?;
+            }
+            writer.flush()?;
+            info!("report generated to {}", file_name.display());
+            files.push(file_name);
+        }
+
+        Ok(files)
+    }
+
+    /// Describe how the report made of `files` was generated in the manifest next to it
+    fn write_manifest(
+        &self,
+        heights: Range<u64>,
+        checkpoint: u64,
+        files: &[PathBuf],
+    ) -> Result<()> {
+        let mut manifest = ReportManifest {
+            felix_version: env!("CARGO_PKG_VERSION"),
+            network: self.network.chain_name(),
+            opcodes: self.opcodes.iter().copied().map(opcode_name).collect(),
+            start_height: heights.start,
+            end_height: heights.end,
+            checkpoint,
+            files: vec![],
+        };
+        for path in files {
+            let mut engine = sha256::Hash::engine();
+            std::io::copy(&mut std::fs::File::open(path)?, &mut engine)?;
+            manifest.files.push(ManifestFile {
+                name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                sha256: sha256::Hash::from_engine(engine).to_string(),
+            });
+        }
+        let file_name = self.output_path(MANIFEST_FILE)?;
+        std::fs::write(&file_name, serde_json::to_string_pretty(&manifest)?)?;
+        info!("manifest written to {}", file_name.display());
+
+        Ok(())
+    }
+
+    /// Report every distinct tapscript spent in `heights`, most spent first
+    fn generate_script_report(
+        &self,
+        format: ReportFormat,
+        heights: Range<u64>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut groups: BTreeMap<String, ScriptGroup> = BTreeMap::new();
+        for height in self.indexed_heights(heights) {
+            for tx_ext in self.report_tx_exts(height?)? {
+                let txid = tx_ext.tx.compute_txid().to_string();
+                for input in tx_ext.inputs.iter() {
+                    let group = groups
+                        .entry(input.hex.clone())
+                        .or_insert_with(|| ScriptGroup {
+                            tapscript_hex: input.hex.clone(),
+                            tapscript_asm: input.asm.clone(),
+                            spends: 0,
+                            txids: vec![],
+                        });
+                    group.spends += 1;
+                    if group.txids.last() != Some(&txid) {
+                        group.txids.push(txid.clone());
+                    }
+                }
+            }
+        }
+        let mut groups: Vec<ScriptGroup> = groups.into_values().collect();
+        // stable sort, ties stay ordered by tapscript hex
+        groups.sort_by_key(|group| std::cmp::Reverse(group.spends));
+        info!("{} distinct tapscripts", groups.len());
+        let mut files = vec![];
+
+        if matches!(format, ReportFormat::Json | ReportFormat::Both) {
+            let file_name = self.output_path("cat_scripts.json")?;
+            std::fs::write(&file_name, serde_json::to_string(&groups)?)?;
+            info!("report generated to {}", file_name.display());
+            files.push(file_name);
+        }
+
+        if format == ReportFormat::Jsonl {
+            let file_name = self.output_path("cat_scripts.jsonl")?;
+            let mut writer = BufWriter::new(std::fs::File::create(&file_name)?);
+            for group in groups.iter() {
+                serde_json::to_writer(&mut writer, group)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+            info!("report generated to {}", file_name.display());
+            files.push(file_name);
+        }
+
+        if matches!(format, ReportFormat::Csv | ReportFormat::Both) {
+            let file_name = self.output_path("cat_scripts.csv")?;
+            let mut writer = csv::Writer::from_path(&file_name)?;
+            for group in groups.iter() {
+                writer.serialize(CsvScriptGroup::from(group))?;
+            }
+            writer.flush()?;
+            info!("report generated to {}", file_name.display());
+            files.push(file_name);
+        }
+
+        Ok(files)
+    }
+
+    /// Path of `file_name` in the output directory, creating the directory if needed
+    fn output_path(&self, file_name: &str) -> Result<PathBuf> {
+        let path = self.output_dir.join(file_name);
+        prepare_output_file(&path)?;
+        Ok(path)
+    }
+
+    pub fn create_plots(&self, options: &PlotOptions) -> Result<()> {
+        let PlotOptions {
+            x_axis,
+            metric,
+            out,
+            y_max,
+            plot_format,
+            plot_mode,
+        } = options.clone();
+        let file_name = match out {
+            Some(out) => {
+                prepare_output_file(&out)?;
+                out
+            }
+            None => self.output_path(match plot_format {
+                PlotFormat::Png => "total_cat_txs.png",
+                PlotFormat::Svg => "total_cat_txs.svg",
+            })?,
+        };
+        // Only what's indexed can be charted, which also keeps plotting working offline
+        let tip = self.indexed_end()?;
+        if metric == Metric::Feerate && !self.has_fees()? {
+            return Err(FelixError::Unsupported(
+                "no indexed tx has a known fee, reindex with --fees or --store-prevouts to plot \
+                 fee rates",
+            ));
+        }
+        let (mut total_cats, y_desc, label) = match metric {
+            Metric::Txs => (
+                self.get_cats_in_range(self.start_block, tip)?,
+                "txs using CAT",
+                "Txs using CAT",
+            ),
+            Metric::CatOps => (
+                self.get_cat_ops_in_range(self.start_block, tip)?,
+                "OP_CAT opcodes",
+                "OP_CATs used",
+            ),
+            Metric::Feerate => (
+                self.get_fee_rates_in_range(self.start_block, tip)?,
+                "median fee rate (sat/vB)",
+                "Median fee rate of txs using CAT",
+            ),
+            Metric::ScriptSize => (
+                self.get_script_sizes_in_range(self.start_block, tip)?,
+                "median tapscript size (bytes)",
+                "Median tapscript size of txs using CAT",
+            ),
+        };
+        if plot_mode == PlotMode::Cumulative
+            && matches!(metric, Metric::Feerate | Metric::ScriptSize)
+        {
+            return Err(FelixError::Unsupported(
+                "a running total of medians is meaningless, plot them per block",
+            ));
+        }
+        let (y_desc, label) = match plot_mode {
+            PlotMode::PerBlock => (y_desc.to_string(), label.to_string()),
+            PlotMode::Cumulative => {
+                let mut running: u64 = 0;
+                for (_, count) in total_cats.iter_mut() {
+                    running = running.saturating_add(*count);
+                    *count = running;
+                }
+                (
+                    format!("cumulative {y_desc}"),
+                    format!("{label} (cumulative)"),
+                )
+            }
+        };
+        // In time mode x is the block timestamp in unix seconds
+        let total_cats = match x_axis {
+            XAxis::Height => total_cats,
+            XAxis::Time => total_cats
+                .into_iter()
+                .map(|(height, count)| Ok((u64::from(self.block_time(height)?), count)))
+                .collect::<Result<Vec<_>>>()?,
+        };
+        // Leave some headroom above the busiest block, and keep an all zero range from collapsing
+        let y_max = y_max.unwrap_or_else(|| {
+            let max = total_cats.iter().map(|(_, y)| *y).max().unwrap_or(0);
+            max.saturating_add(max.div_ceil(10)).max(1)
+        });
+        let plot = Plot {
+            points: total_cats,
+            x_axis,
+            y_max,
+            y_desc,
+            label,
+        };
+
+        match plot_format {
+            PlotFormat::Png => draw_plot(
+                BitMapBackend::new(&file_name, (1500, 800)).into_drawing_area(),
+                &plot,
+            )?,
+            PlotFormat::Svg => draw_plot(
+                SVGBackend::new(&file_name, (1500, 800)).into_drawing_area(),
+                &plot,
+            )?,
+        }
+        info!("plot written to {}", file_name.display());
+        Ok(())
+    }
+}
+
+/// A chart ready to be drawn on any plotters backend
+struct Plot {
+    /// x is a height or a unix timestamp depending on `x_axis`
+    points: Vec<(u64, u64)>,
+    x_axis: XAxis,
+    y_max: u64,
+    y_desc: String,
+    label: String,
+}
+
+/// Check that `path` can be written before spending time on its contents, creating its parent
+/// directory if needed. Fails when the directory is in fact a file, the path is a directory, or
+/// either isn't writable
+fn prepare_output_file(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if dir.exists() && !dir.is_dir() {
+            return Err(FelixError::OutputDir {
+                path: dir.to_path_buf(),
+                source: std::io::Error::other("a file is in the way"),
+            });
+        }
+        std::fs::create_dir_all(dir).map_err(|source| FelixError::OutputDir {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+    }
+    if path.is_dir() {
+        return Err(FelixError::OutputFile {
+            path: path.to_path_buf(),
+            source: std::io::Error::other("it is a directory"),
+        });
+    }
+    // Opening without truncating leaves an existing file intact for appends
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|source| FelixError::OutputFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    Ok(())
+}
+
+fn draw_plot<DB>(root: DrawingArea<DB, Shift>, plot: &Plot) -> Result<()>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let x_min = plot.points.iter().map(|(x, _)| *x).min().unwrap_or(0);
+    let x_max = plot.points.iter().map(|(x, _)| *x).max().unwrap_or(0);
+    let x_range = x_min..x_max.saturating_add(1);
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption("CATS over time", ("sans-serif", 50).into_font())
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(x_range, 0..plot.y_max)?;
+
+    let format_x = |x: &u64| match plot.x_axis {
+        XAxis::Height => x.to_string(),
+        XAxis::Time => i64::try_from(*x)
+            .ok()
+            .and_then(|x| DateTime::from_timestamp(x, 0))
+            .map(|time| time.format("%Y-%m-%d").to_string())
+            .unwrap_or_default(),
+    };
+    chart
+        .configure_mesh()
+        .x_label_formatter(&format_x)
+        .x_desc(match plot.x_axis {
+            XAxis::Height => "block heights",
+            XAxis::Time => "block time",
+        })
+        .y_desc(&plot.y_desc)
+        .draw()?;
+
+    chart
+        .draw_series(LineSeries::new(plot.points.iter().copied(), RED))?
+        .label(&plot.label)
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+/// The CBOR of a value in the txs tree, decompressed if it was written with --compress
+fn tx_ext_cbor(bytes: &[u8]) -> Result<std::borrow::Cow<'_, [u8]>> {
+    match bytes.split_first() {
+        Some((&COMPRESSED_MAGIC, compressed)) => Ok(zstd::decode_all(compressed)?.into()),
+        _ => Ok(bytes.into()),
+    }
+}
+
+fn decode_tx_ext(bytes: &[u8]) -> Result<TransactionExt> {
+    Ok(ciborium::from_reader(tx_ext_cbor(bytes)?.as_ref())?)
+}
+
+/// Describe a matched transaction from the witness scripts of its candidate inputs, given along
+/// with their input index
+fn build_tx_ext(
+    height: u64,
+    block_time: u32,
+    tx: &Transaction,
+    spends: &[(u32, WitnessScript)],
+    opcodes: &[Opcode],
+) -> TransactionExt {
+    let mut inputs = vec![];
+    let mut matched = vec![];
+    let mut cat_ops = 0;
+    let mut malformed = false;
+    for (input, spend) in spends {
+        let tapscript = spend.script;
+        let script_opcodes = script_matched_opcodes(tapscript, opcodes);
+        if script_opcodes.is_empty() {
+            continue;
+        }
+        malformed |= tapscript
+            .instructions()
+            .any(|instruction| instruction.is_err());
+        cat_ops += count_cat_opcodes(tapscript);
+        for opcode in script_opcodes {
+            let name = opcode_name(opcode);
+            if !matched.contains(&name) {
+                matched.push(name);
+            }
+        }
+        inputs.push(InputTapscript {
+            input: *input,
+            asm: tapscript.to_asm_string(),
+            hex: tapscript.to_hex_string(),
+            leaf_version: spend.leaf_version,
+            annex: spend.annex.map(hex::encode),
+            witness_index: Some(spend.witness_index as u32),
+            size: Some(tapscript.len() as u32),
+            witness_items: tx
+                .input
+                .get(*input as usize)
+                .map(|txin| witness::item_count(&txin.witness) as u32),
+        });
+    }
+
+    let mut tx_ext = TransactionExt {
+        height,
+        block_time,
+        size: tx.total_size() as u64,
+        vsize: tx.vsize() as u64,
+        fee: None,
+        malformed,
+        output_types: tx
+            .output
+            .iter()
+            .map(|output| OutputType::of(&output.script_pubkey))
+            .collect(),
+        matched_inputs: vec![],
+        scripts_asm: inputs.iter().map(|input| input.asm.clone()).collect(),
+        scripts_hex: inputs.iter().map(|input| input.hex.clone()).collect(),
+        inputs,
+        opcodes: matched,
+        cat_ops,
+        tx: tx.clone(),
+    };
+    tx_ext.matched_inputs = tx_ext.matched_inputs();
+    tx_ext
+}
+
+/// A parsed `find_script` query
+enum ScriptPattern {
+    Hex(String),
+    Opcodes(MatchMode, Vec<Opcode>),
+}
+
+impl ScriptPattern {
+    /// Whether the script stored as `hex` matches. Data pushes never match an opcode of the
+    /// pattern, so a run of opcodes only matches when nothing is pushed in between
+    fn matches(&self, hex: &str) -> bool {
+        let (mode, pattern) = match self {
+            ScriptPattern::Hex(query) => return hex.eq_ignore_ascii_case(query),
+            ScriptPattern::Opcodes(mode, pattern) => (mode, pattern),
+        };
+        let Ok(bytes) = hex::decode(hex) else {
+            return false;
+        };
+        let ops: Vec<Option<Opcode>> = Script::from_bytes(&bytes)
+            .instructions()
+            .map(|instruction| match instruction {
+                Ok(Instruction::Op(op)) => Some(op),
+                _ => None,
+            })
+            .collect();
+        let is_run = |window: &[Option<Opcode>]| {
+            window
+                .iter()
+                .zip(pattern.iter())
+                .all(|(op, expected)| *op == Some(*expected))
+        };
+        if ops.len() < pattern.len() {
+            return false;
+        }
+        match mode {
+            MatchMode::StartsWith => is_run(&ops[..pattern.len()]),
+            MatchMode::EndsWith => is_run(&ops[ops.len() - pattern.len()..]),
+            _ => ops.windows(pattern.len()).any(is_run),
+        }
+    }
+}
+
+/// Key of an outpoint in the prevouts tree, the txid followed by the big endian vout
+fn prevout_key(outpoint: &OutPoint) -> [u8; 36] {
+    let mut key = [0; 36];
+    key[..32].copy_from_slice(outpoint.txid.as_byte_array());
+    key[32..].copy_from_slice(&outpoint.vout.to_be_bytes());
+    key
+}
+
+/// Nearest rank `percent`th percentile of sorted values, 0 when there are none
+fn percentile(sorted: &[u32], percent: usize) -> u32 {
+    match sorted.len() {
+        0 => 0,
+        len => sorted[(len * percent).div_ceil(100).max(1) - 1],
+    }
+}
+
+/// Whether the tapscript of a script path spend executes any of `opcodes`, the check indexing
+/// applies to every input unless every witness element is scanned. Witnesses without a tapscript,
+/// like key path spends, never match
+pub fn witness_uses_opcodes(witness: &Witness, opcodes: &[Opcode]) -> bool {
+    match witness::parse(witness) {
+        Some(spend) => !script_matched_opcodes(spend.script, opcodes).is_empty(),
+        None => false,
+    }
+}
+
+/// The opcodes out of `opcodes` the script actually executes, in order of first use. Push data is
+/// skipped by the instruction iterator so pushed bytes that happen to equal an opcode don't count
+pub fn script_matched_opcodes(script: &Script, opcodes: &[Opcode]) -> Vec<Opcode> {
+    let mut matched = vec![];
+    for instruction in script.instructions() {
+        if let Ok(Instruction::Op(op)) = instruction {
+            if opcodes.contains(&op) && !matched.contains(&op) {
+                matched.push(op);
+            }
+        }
+    }
+    matched
+}
+
+/// Whether the script executes OP_CAT, `script_matched_opcodes` for just that opcode
+pub fn script_uses_cat(script: &Script) -> bool {
+    !script_matched_opcodes(script, &[OP_CAT]).is_empty()
+}
+
+/// Whether the witness is a script path spend whose tapscript executes OP_CAT, see
+/// `witness_uses_opcodes`
+pub fn witness_includes_cat(witness: &Witness) -> bool {
+    witness_uses_opcodes(witness, &[OP_CAT])
+}
+
+/// Run the detector over a witness the way indexing does: its tapscript, or with
+/// `scan_all_witness_items` every element that parses as a script
+pub fn detect_witness(
+    witness: &Witness,
+    opcodes: &[Opcode],
+    scan_all_witness_items: bool,
+) -> Vec<Detection> {
+    let scripts = if scan_all_witness_items {
+        witness::scripts(witness)
+    } else {
+        witness::parse(witness)
+            .map(WitnessScript::from)
+            .into_iter()
+            .collect()
+    };
+    scripts
+        .into_iter()
+        .map(|script| Detection {
+            witness_index: Some(script.witness_index),
+            ..detect_script(script.script, opcodes)
+        })
+        .collect()
+}
+
+/// Run the detector over a lone tapscript
+pub fn detect_script(script: &Script, opcodes: &[Opcode]) -> Detection {
+    Detection {
+        witness_index: None,
+        hex: script.to_hex_string(),
+        asm: script.to_asm_string(),
+        opcodes: script_matched_opcodes(script, opcodes)
+            .into_iter()
+            .map(opcode_name)
+            .collect(),
+    }
+}
+
+/// Number of OP_CAT opcodes the script executes, push data is skipped by the instruction iterator
+pub fn count_cat_opcodes(script: &Script) -> usize {
+    script
+        .instructions()
+        .filter(|instruction| matches!(instruction, Ok(Instruction::Op(op)) if *op == OP_CAT))
+        .count()
+}
+
+/// End of the heights an index holds, the checkpoint unless hashes were stored above it
+fn indexed_end(checkpoint: u64, hashes: &Tree) -> Result<u64> {
+    match hashes.last()? {
+        Some((key, _)) => {
+            let highest = u64::from_be_bytes(key.as_ref().try_into()?);
+            Ok(checkpoint.max(highest + 1))
+        }
+        None => Ok(checkpoint),
+    }
+}
+
+/// The txids indexed at `height`, from the heights tree of an index
+fn block_txids(heights: &Tree, height: u64) -> Result<Vec<Txid>> {
+    match heights.get(height.to_be_bytes())? {
+        Some(bytes) => Ok(ciborium::from_reader(bytes.as_ref())?),
+        None => Ok(vec![]),
+    }
+}
+
+/// Prefix of the sled trees holding the index of `opcodes`, so indexes of different opcode sets
+/// can share a db. None for OP_CAT alone, whose index lives in the unprefixed trees
+pub fn namespace(opcodes: &[Opcode]) -> Option<String> {
+    let mut opcodes = opcodes.to_vec();
+    opcodes.sort_by_key(|opcode| opcode.to_u8());
+    opcodes.dedup();
+    if opcodes == [OP_CAT] {
+        return None;
+    }
+    Some(
+        opcodes
+            .into_iter()
+            .map(opcode_name)
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+}
+
+/// Parse a `start:end` height range as given to --range
+pub fn parse_height_range(range: &str) -> Result<Range<u64>> {
+    let invalid = || FelixError::HeightRange(range.to_string());
+    let (start, end) = range.split_once(':').ok_or_else(invalid)?;
+    let start = start.trim().parse::<u64>().map_err(|_| invalid())?;
+    let end = end.trim().parse::<u64>().map_err(|_| invalid())?;
+    if start >= end {
+        return Err(invalid());
+    }
+    Ok(start..end)
+}
+
+/// Parse hex as pasted from a block explorer, surrounding whitespace is ignored
+pub fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+    hex::decode(hex.trim()).map_err(|_| FelixError::Hex(hex.to_string()))
+}
+
+/// Parse an opcode name like `OP_CAT` or `cat`, including the soft fork names in OPCODE_ALIASES
+pub fn parse_opcode(name: &str) -> Result<Opcode> {
+    let name = name.trim().to_uppercase();
+    let name = if name.starts_with("OP_") {
+        name
+    } else {
+        format!("OP_{}", name)
+    };
+
+    if let Some((_, opcode)) = OPCODE_ALIASES.iter().find(|(alias, _)| *alias == name) {
+        return Ok(*opcode);
+    }
+    (0..=u8::MAX)
+        .map(Opcode::from)
+        .find(|opcode| opcode.to_string() == name)
+        .ok_or(FelixError::UnknownOpcode(name))
+}
+
+pub fn opcode_name(opcode: Opcode) -> String {
+    match OPCODE_ALIASES.iter().find(|(_, alias)| *alias == opcode) {
+        Some((name, _)) => name.to_string(),
+        None => opcode.to_string(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use bitcoin::opcodes::all::OP_EQUAL;
+    use bitcoin::script::Builder;
+    use bitcoin::ScriptBuf;
+
+    use super::*;
+    use crate::source::MemorySource;
+    use crate::test_util::{cat_spend, chain, key_path_spend, test_app, test_app_at};
+
+    /// Index everything `source` offers and return the heights handed to `on_block`
+    pub(crate) fn index(app: &App, source: &MemorySource) -> Vec<u64> {
+        let mut heights = vec![];
+        app.start_index_with(source, &IndexOptions::default(), |height, _| {
+            heights.push(height)
+        })
+        .expect("index");
+        heights
+    }
+
+    #[test]
+    fn indexes_fresh_db() {
+        let app = test_app(0);
+        let blocks = chain(
+            vec![
+                vec![],
+                vec![cat_spend(1), key_path_spend(2)],
+                vec![key_path_spend(3)],
+                vec![cat_spend(4), cat_spend(5)],
+                vec![],
+            ],
+            0,
+        );
+        // indexing stops before the tip
+        assert_eq!(
+            index(&app, &MemorySource::new(0, blocks.clone())),
+            [0, 1, 2, 3]
+        );
+
+        assert_eq!(app.retrieve_check_point().unwrap(), 4);
+        assert_eq!(app.get_total_cat_txs().unwrap(), 3);
+        assert_eq!(
+            app.get_cats_in_range(0, 4).unwrap(),
+            [(0, 0), (1, 1), (2, 0), (3, 2)]
+        );
+        let txid = cat_spend(1).compute_txid();
+        let tx_ext = app.get_tx(&txid).unwrap().expect("indexed cat spend");
+        assert_eq!(tx_ext.height, 1);
+        assert_eq!(tx_ext.opcodes, ["OP_CAT"]);
+        assert!(app
+            .get_tx(&key_path_spend(2).compute_txid())
+            .unwrap()
+            .is_none());
+        for (height, block) in blocks.iter().enumerate().take(4) {
+            assert_eq!(
+                app.retrieve_block_hash(height as u64).unwrap(),
+                Some(block.block_hash())
+            );
+        }
+    }
+
+    #[test]
+    fn resumes_from_checkpoint() {
+        let app = test_app(0);
+        let blocks = chain(
+            vec![
+                vec![cat_spend(1)],
+                vec![],
+                vec![cat_spend(2)],
+                vec![cat_spend(3)],
+                vec![],
+            ],
+            0,
+        );
+        assert_eq!(
+            index(&app, &MemorySource::new(0, blocks[..3].to_vec())),
+            [0, 1]
+        );
+        assert_eq!(app.retrieve_check_point().unwrap(), 2);
+
+        // a later run over a longer chain only picks up the new blocks
+        assert_eq!(index(&app, &MemorySource::new(0, blocks)), [2, 3]);
+        assert_eq!(app.retrieve_check_point().unwrap(), 4);
+        assert_eq!(app.get_total_cat_txs().unwrap(), 3);
+    }
+
+    #[test]
+    fn fetches_next_batch_while_scanning() {
+        /// Remembers the highest height fetched
+        struct Recording(MemorySource, std::sync::atomic::AtomicU64);
+
+        impl BlockSource for Recording {
+            fn tip(&self) -> Result<u64> {
+                self.0.tip()
+            }
+
+            fn block_at(&self, height: u64) -> Result<(BlockHash, Block)> {
+                self.1.fetch_max(height, Ordering::SeqCst);
+                self.0.block_at(height)
+            }
+        }
+
+        let app = test_app(0);
+        let source = Recording(
+            MemorySource::new(0, chain(vec![vec![]; 6], 0)),
+            Default::default(),
+        );
+        let mut fetched_ahead = false;
+        app.start_index_with(&source, &IndexOptions::default(), |height, _| {
+            if height == 0 {
+                // the batch after 0..2 comes in without the indexer asking for it
+                let waiting = Instant::now();
+                while !fetched_ahead && waiting.elapsed() < Duration::from_secs(5) {
+                    fetched_ahead = source.1.load(Ordering::SeqCst) >= 3;
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            }
+        })
+        .unwrap();
+        assert!(fetched_ahead);
+        assert_eq!(app.retrieve_check_point().unwrap(), 5);
+    }
+
+    #[test]
+    fn rolls_back_reorg() {
+        let app = test_app(0);
+        let stale = chain(
+            vec![
+                vec![],
+                vec![cat_spend(1)],
+                vec![cat_spend(2)],
+                vec![cat_spend(3)],
+                vec![],
+            ],
+            0,
+        );
+        index(&app, &MemorySource::new(0, stale.clone()));
+        assert_eq!(app.get_total_cat_txs().unwrap(), 3);
+
+        // the active chain forks off after height 1
+        let mut active = stale[..2].to_vec();
+        let mut prev_blockhash = active[1].block_hash();
+        for (fork, txdata) in [
+            vec![],
+            vec![cat_spend(4)],
+            vec![cat_spend(5), cat_spend(6)],
+            vec![],
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut block = chain(vec![txdata], fork as u32 + 1).remove(0);
+            block.header.prev_blockhash = prev_blockhash;
+            prev_blockhash = block.block_hash();
+            active.push(block);
+        }
+        assert_eq!(
+            index(&app, &MemorySource::new(0, active.clone())),
+            [2, 3, 4]
+        );
+
+        assert_eq!(app.retrieve_check_point().unwrap(), 5);
+        for (height, block) in active.iter().enumerate().take(5) {
+            assert_eq!(
+                app.retrieve_block_hash(height as u64).unwrap(),
+                Some(block.block_hash())
+            );
+        }
+        assert_eq!(
+            app.get_cats_in_range(0, 5).unwrap(),
+            [(0, 0), (1, 1), (2, 0), (3, 1), (4, 2)]
+        );
+        assert_eq!(app.get_total_cat_txs().unwrap(), 4);
+        for tag in [2, 3] {
+            assert!(app
+                .get_tx(&cat_spend(tag).compute_txid())
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn restarts_at_stored_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let blocks = chain(
+            vec![
+                vec![cat_spend(1)],
+                vec![cat_spend(2)],
+                vec![cat_spend(3)],
+                vec![cat_spend(4)],
+                vec![],
+            ],
+            0,
+        );
+        {
+            let app = test_app_at(dir.path(), 0);
+            assert_eq!(
+                index(&app, &MemorySource::new(0, blocks[..3].to_vec())),
+                [0, 1]
+            );
+        }
+
+        // the checkpoint is the next height to index, the last indexed one isn't processed again
+        let app = test_app_at(dir.path(), 0);
+        assert_eq!(app.retrieve_check_point().unwrap(), 2);
+        assert_eq!(index(&app, &MemorySource::new(0, blocks)), [2, 3]);
+        assert_eq!(app.get_total_cat_txs().unwrap(), 4);
+    }
+
+    #[test]
+    fn migrates_legacy_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            // dbs from before the checkpoint version stored the last indexed height
+            let app = test_app_at(dir.path(), 0);
+            app.meta.remove(CHECKPOINT_VERSION_SLED_KEY).unwrap();
+            app.insert_check_point(1).unwrap();
+        }
+
+        let app = test_app_at(dir.path(), 0);
+        assert_eq!(app.retrieve_check_point().unwrap(), 2);
+        let blocks = chain(vec![vec![], vec![], vec![], vec![cat_spend(1)], vec![]], 0);
+        assert_eq!(index(&app, &MemorySource::new(0, blocks)), [2, 3]);
+    }
+
+    #[test]
+    fn diffs_against_read_only_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let other_path = dir.path().join("other");
+        {
+            let other = test_app_at(&other_path, 0);
+            let blocks = chain(vec![vec![cat_spend(1)], vec![cat_spend(2)], vec![]], 0);
+            index(&other, &MemorySource::new(0, blocks));
+        }
+        let checksum = || sled::open(&other_path).unwrap().checksum().unwrap();
+        let before = checksum();
+
+        let app = test_app(0);
+        let blocks = chain(vec![vec![cat_spend(1)], vec![cat_spend(3)], vec![]], 0);
+        index(&app, &MemorySource::new(0, blocks));
+        let other = IndexView::open(&other_path, &[OP_CAT], Network::Regtest).unwrap();
+        let diff = app.diff(&other).unwrap();
+        assert_eq!((diff.start, diff.end), (0, 2));
+        assert_eq!(diff.differing, [1]);
+        assert_eq!((diff.only_here, diff.only_other), (1, 1));
+        drop(other);
+        assert_eq!(checksum(), before);
+
+        // an index of other opcodes isn't created on the way
+        assert!(matches!(
+            IndexView::open(&other_path, &[OP_CAT, OP_EQUAL], Network::Regtest),
+            Err(FelixError::NoIndex { .. })
+        ));
+        assert_eq!(checksum(), before);
+    }
+
+    #[test]
+    fn refuses_fee_rate_plot_without_fees() {
+        let app = test_app(0);
+        let blocks = chain(vec![vec![cat_spend(1)], vec![]], 0);
+        index(&app, &MemorySource::new(0, blocks));
+
+        let dir = tempfile::tempdir().unwrap();
+        let err = app
+            .create_plots(&PlotOptions {
+                x_axis: XAxis::Height,
+                metric: Metric::Feerate,
+                out: Some(dir.path().join("fee_rates.png")),
+                y_max: None,
+                plot_format: PlotFormat::Png,
+                plot_mode: PlotMode::PerBlock,
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("--fees"));
+    }
+
+    #[test]
+    fn retrieves_check_point() {
+        let app = test_app(7);
+        // nothing indexed yet, start from the start block
+        assert_eq!(app.retrieve_check_point().unwrap(), 7);
+
+        app.insert_check_point(12).unwrap();
+        assert_eq!(app.retrieve_check_point().unwrap(), 12);
+    }
+
+    #[test]
+    fn counts_without_decoding_txs() {
+        let app = test_app(0);
+        let blocks = chain(vec![vec![cat_spend(1), cat_spend(2)], vec![]], 0);
+        index(&app, &MemorySource::new(0, blocks));
+
+        // no tx body decodes anymore, counting only reads the totals and the txids by height
+        for txid in [cat_spend(1).compute_txid(), cat_spend(2).compute_txid()] {
+            app.txs.insert(txid.to_string(), &[0xff; 8]).unwrap();
+            assert!(app.get_tx(&txid).is_err());
+        }
+        assert_eq!(app.get_total_cat_txs().unwrap(), 2);
+        assert_eq!(app.get_cats_in_range(0, 1).unwrap(), [(0, 2)]);
+    }
+
+    #[test]
+    fn series_above_i32_heights() {
+        // past u32::MAX too, heights are u64 all the way through
+        let start = i32::MAX as u64 + u32::MAX as u64;
+        let app = test_app(start);
+        let blocks = chain(
+            vec![
+                vec![cat_spend(1)],
+                vec![],
+                vec![cat_spend(2), cat_spend(3)],
+                vec![],
+            ],
+            0,
+        );
+        index(&app, &MemorySource::new(start, blocks));
+
+        let series = app.series(start, start + 3).unwrap();
+        let series: Vec<(u64, u64)> = series
+            .iter()
+            .map(|block| (block.height, block.count))
+            .collect();
+        assert_eq!(series, [(start, 1), (start + 1, 0), (start + 2, 2)]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("plot.svg");
+        app.create_plots(&PlotOptions {
+            x_axis: XAxis::Height,
+            metric: Metric::Txs,
+            out: Some(out.clone()),
+            y_max: None,
+            plot_format: PlotFormat::Svg,
+            plot_mode: PlotMode::PerBlock,
+        })
+        .unwrap();
+        assert!(std::fs::read_to_string(out)
+            .unwrap()
+            .contains(&(start + 2).to_string()));
+    }
+
+    #[test]
+    fn reports_every_indexed_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = test_app(0);
+        app.output_dir = dir.path().to_path_buf();
+        let blocks = chain(
+            vec![
+                vec![cat_spend(1)],
+                vec![cat_spend(2), key_path_spend(3)],
+                vec![cat_spend(4)],
+                vec![],
+            ],
+            0,
+        );
+        index(&app, &MemorySource::new(0, blocks));
+
+        // the default range is the whole index, even below 100 blocks
+        app.generate_cat_report(&ReportOptions {
+            format: ReportFormat::Json,
+            report_start: None,
+            report_end: None,
+            group_by_script: false,
+            append: false,
+            malformed: false,
+        })
+        .unwrap();
+        let report: Vec<TransactionExt> =
+            serde_json::from_slice(&std::fs::read(dir.path().join("cat_txs.json")).unwrap())
+                .unwrap();
+        let txids: Vec<(u64, Txid)> = report
+            .iter()
+            .map(|tx_ext| (tx_ext.height, tx_ext.tx.compute_txid()))
+            .collect();
+        assert_eq!(
+            txids,
+            [
+                (0, cat_spend(1).compute_txid()),
+                (1, cat_spend(2).compute_txid()),
+                (2, cat_spend(4).compute_txid()),
+            ]
+        );
+        assert!(dir.path().join(MANIFEST_FILE).exists());
+    }
+
+    #[test]
+    fn script_uses_cat_only_when_executed() {
+        let cat = Builder::new().push_opcode(OP_CAT).into_script();
+        assert!(script_uses_cat(&cat));
+        // the opcode byte as push data isn't executed
+        let pushed = Builder::new().push_slice([OP_CAT.to_u8()]).into_script();
+        assert!(!script_uses_cat(&pushed));
+        assert!(!script_uses_cat(&ScriptBuf::new()));
+    }
+
+    #[test]
+    fn witness_includes_cat_of_script_path_spends() {
+        assert!(witness_includes_cat(&cat_spend(1).input[0].witness));
+        assert!(!witness_includes_cat(&key_path_spend(2).input[0].witness));
+        assert!(!witness_includes_cat(&Witness::new()));
+    }
+}
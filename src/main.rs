@@ -1,324 +1,1050 @@
-use std::collections::HashSet;
-
-use anyhow::Result;
-use bitcoin::{Block, Script, Transaction, Witness};
-use bitcoincore_rpc::{Auth, Client as BitcoinRpc, RpcApi};
-use ciborium;
-use clap::Parser;
-use log::{debug, info};
-use plotters::prelude::*;
-use serde::{Deserialize, Serialize};
-use sled::Db;
-
-/// Sled key for checkpoint
-const CHECKPOINT_SLED_KEY: &str = "CHECKPOINT";
-/// tip - BLOCK_DEPTH is when the indexer will stop. This is to avoid reorgs
-/// even signet reorgs
-const BLOCK_DEPTH: u64 = 6;
-
-/// Simple program to greet a person
+use std::io::Write;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::opcodes::Opcode;
+use bitcoin::{BlockHash, Script, Witness};
+use chrono::DateTime;
+use clap::{Parser, Subcommand, ValueEnum};
+use felix::source::{BlockSource, FileSource};
+use felix::{
+    detect_script, detect_witness, parse_height_range, parse_hex, parse_opcode, server, App, Auth,
+    Config, IndexOptions, IndexView, MatchMode, Network, PlotOptions, ReportOptions,
+    IN_MEMORY_DB_PATH,
+};
+use log::{error, info, warn, LevelFilter};
+use serde::Deserialize;
+use serde_json::json;
+
+const DEFAULT_DB_PATH: &str = "db";
+const BITCOIND_PASSWORD_ENV: &str = "BITCOIND_PASSWORD";
+
+/// Index of the taproot script path spends using OP_CAT
 #[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// bitcoind url
-    #[arg(long)]
-    bitcoind_url: String,
+    /// toml file to read any of the bitcoind options, start block and db path from. Flags
+    /// given on the command line take precedence
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
 
-    /// bitcoind url
-    #[arg(long)]
-    bitcoind_port: String,
+    /// bitcoind host, or a comma separated list of hosts to fail over between. A host can carry
+    /// its own port, e.g. 10.0.0.2:38332
+    #[arg(long, global = true)]
+    bitcoind_url: Option<String>,
+
+    /// bitcoind rpc port
+    #[arg(long, global = true)]
+    bitcoind_port: Option<String>,
 
     /// bitcoind user
-    #[arg(long)]
-    bitcoind_username: String,
+    #[arg(long, global = true)]
+    bitcoind_username: Option<String>,
 
-    /// bitcoind url
-    #[arg(long)]
-    bitcoind_password: String,
+    /// bitcoind password, visible to other users of the host, prefer --bitcoind-password-file or
+    /// the BITCOIND_PASSWORD environment variable
+    #[arg(long, global = true)]
+    bitcoind_password: Option<String>,
 
-    /// optional starting block, default is 193536
-    #[arg(long, default_value = "193536")]
-    start_block: u64,
+    /// file holding the bitcoind password, trailing newlines are ignored
+    #[arg(long, global = true, conflicts_with = "bitcoind_password")]
+    bitcoind_password_file: Option<PathBuf>,
 
-    /// db path
-    #[arg(long, default_value = "db")]
-    db_path: String,
+    /// bitcoind cookie file, used instead of username and password
+    #[arg(long, global = true)]
+    bitcoind_cookie: Option<PathBuf>,
 
-    #[arg()]
-    command: String,
-}
+    /// network bitcoind is expected to run on
+    #[arg(long, global = true, value_enum, default_value = "signet")]
+    network: Network,
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct TransactionExt {
-    height: u64,
-    // tx size
-    size: u64,
-    // human readable tapscript, per input
-    scripts_asm: Vec<String>,
-    // tapscript as hex, per input
-    scripts_hex: Vec<String>,
-    tx: Transaction,
-}
+    /// optional starting block, defaults to the one the db was first indexed from, or a per
+    /// network height (193536 on signet) for a new db
+    #[arg(long, global = true)]
+    start_block: Option<u64>,
+
+    /// hash of the starting block, resolved to its height on the node's active chain
+    #[arg(long, global = true, conflicts_with = "start_block")]
+    start_block_hash: Option<BlockHash>,
+
+    /// use --start-block even if the db was indexed from a different one
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// db path, defaults to db. :memory: uses a temporary db dropped on exit
+    #[arg(long, global = true)]
+    db_path: Option<String>,
+
+    /// use a temporary db dropped on exit, same as --db-path :memory:
+    #[arg(long, global = true, conflicts_with = "db_path")]
+    in_memory: bool,
+
+    /// blocks below the tip indexing stops at to stay clear of reorgs, 0 indexes up to the tip
+    #[arg(long, global = true, default_value = "6")]
+    confirmations: u64,
+
+    /// number of blocks fetched from bitcoind concurrently ahead of the parser
+    #[arg(long, global = true, default_value = "1")]
+    rpc_concurrency: u64,
+
+    /// times a bitcoind request failing with a timeout, a dropped connection or a node still
+    /// warming up is retried, with exponential backoff
+    #[arg(long, global = true, default_value = "3")]
+    rpc_max_retries: u32,
+
+    /// threads scanning a block's transactions, 0 uses one per core. Only applies without
+    /// --strict-prevout-check, whose rpc lookups keep the scan serial
+    #[arg(long, global = true, default_value = "0")]
+    parse_threads: usize,
+
+    /// comma separated opcodes a tapscript has to use to be indexed, e.g. OP_CAT,OP_CSFS
+    #[arg(long, global = true, value_delimiter = ',', value_parser = parse_opcode, default_value = "OP_CAT")]
+    opcodes: Vec<Opcode>,
+
+    /// directory reports and plots are written to
+    #[arg(long, global = true, default_value = "output")]
+    output_dir: PathBuf,
+
+    /// only index tapscript spends whose witness carries an annex
+    #[arg(long, global = true)]
+    with_annex: bool,
+
+    /// look for the opcodes in every witness element that parses as a script, e.g. P2WSH witness
+    /// scripts, instead of only in tapscripts. Matches record the index of their element
+    #[arg(long, global = true)]
+    scan_all_witness_items: bool,
+
+    /// zstd compress the txs written to the db. Compressed and uncompressed txs can be mixed in
+    /// one db
+    #[arg(long, global = true)]
+    compress: bool,
+
+    /// most verbose level logged: off, error, warn, info, debug or trace
+    #[arg(long, global = true, default_value = "info")]
+    log_level: LevelFilter,
 
-struct App {
-    bitcoind_rpc: BitcoinRpc,
-    start_block: u64,
-    db: Db,
+    /// append logs to this file instead of writing them to stderr. It's opened in append mode so
+    /// it can be rotated externally, e.g. by logrotate with copytruncate
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// format of the log lines
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    log_format: LogFormat,
+
+    /// print command output as json on stdout, logs still go to stderr
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// also confirm with bitcoind that the prevout of every matching input is P2TR. The witness
+    /// control block already proves a taproot spend, so this is off by default. Run bitcoind
+    /// with -txindex, prevouts it can't find are left unchecked
+    #[arg(long, global = true)]
+    strict_prevout_check: bool,
+
+    #[command(subcommand)]
+    command: Command,
 }
 
-impl App {
-    fn new(args: Args) -> Self {
-        let auth = Auth::UserPass(args.bitcoind_username, args.bitcoind_password);
-        let bitcoind_rpc = BitcoinRpc::new(
-            format!("http://{}:{}", args.bitcoind_url, args.bitcoind_port).as_str(),
-            auth,
-        )
-        .expect("connect to bitcoind");
-        // test the connection
-        bitcoind_rpc.get_block_count().expect("get block count");
-        info!("opening db at: {}", args.db_path);
-        Self {
-            bitcoind_rpc,
-            start_block: args.start_block,
-            db: sled::open(args.db_path).expect("open db"),
-        }
-    }
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// index blocks from the checkpoint up to the tip
+    #[command(visible_alias = "start_index")]
+    Index {
+        #[command(flatten)]
+        options: IndexOptions,
 
-    fn start_index(&mut self) -> Result<()> {
-        // get tip
-        let tip = self.bitcoind_rpc.get_block_count()?;
-        let index_till = tip - BLOCK_DEPTH;
+        /// serve prometheus metrics on this address while indexing
+        #[arg(long)]
+        metrics_listen: Option<SocketAddr>,
 
-        // get checkpoint
-        let checkpoint = self.retrieve_check_point()?;
-        info!("Current checkpoint height: {}", checkpoint);
+        /// index from bitcoind's blocks dir, or a single file of blocks in the same format,
+        /// instead of over rpc. Fees aren't looked up without bitcoind
+        #[arg(long, conflicts_with = "strict_prevout_check")]
+        block_files: Option<PathBuf>,
+    },
+    /// index up to the tip and keep following it, printing every cat tx as it gets indexed
+    Tail {
+        /// seconds to wait between polls for new blocks
+        #[arg(long, default_value = "60")]
+        poll_interval: u64,
+    },
+    /// print the checkpoint and the node's tip
+    #[command(visible_alias = "get_checkpoint")]
+    Checkpoint,
+    /// print how the last indexing run went
+    #[command(name = "last_sync", visible_alias = "last-sync")]
+    LastSync,
+    /// print how far the checkpoint trails the indexing target
+    Lag {
+        /// exit with an error when the checkpoint is more than this many blocks behind
+        #[arg(long)]
+        max_lag: Option<u64>,
+    },
+    /// print the number of indexed txs
+    #[command(name = "total_cat_txs", visible_alias = "get_total_cat_txs")]
+    TotalCatTxs,
+    /// time the opcode matching over a range of blocks, without any db writes or prevout lookups
+    #[command(name = "bench_detector", visible_alias = "bench-detector")]
+    BenchDetector {
+        /// heights start:end (end exclusive) to fetch and scan
+        #[arg(long, value_parser = parse_height_range)]
+        range: Range<u64>,
+    },
+    /// list the unconfirmed txs in the node's mempool using the indexed opcodes, without
+    /// indexing them
+    Mempool {
+        /// keep listing the mempool matches every --poll-interval seconds
+        #[arg(long)]
+        follow: bool,
 
-        for height in checkpoint..index_till {
-            let block = self.bitcoind_rpc.get_block_hash(height)?;
-            let block = self.bitcoind_rpc.get_block(&block)?;
-            self.parse_block(height, block)?;
-            self.insert_check_point(height)?;
-        }
+        /// seconds between mempool scans with --follow
+        #[arg(long, default_value = "60")]
+        poll_interval: u64,
+    },
+    /// print what was indexed for a tx
+    #[command(name = "get_tx")]
+    GetTx {
+        /// full txid, or a prefix of at least 6 hex chars matching every indexed tx it starts
+        txid: String,
 
-        Ok(())
-    }
+        /// fail instead of printing every match when the prefix matches more than one tx
+        #[arg(long)]
+        unique: bool,
+    },
+    /// list the indexed txs spending a tapscript
+    #[command(name = "find_script", visible_alias = "find-script")]
+    FindScript {
+        /// tapscript hex, or with --match other than exact a space separated run of opcodes such
+        /// as "OP_CAT OP_EQUAL"
+        query: String,
 
-    fn insert_check_point(&mut self, height: u64) -> Result<()> {
-        let mut bytes = Vec::new();
-        ciborium::into_writer(&height, &mut bytes)?;
-        self.db.insert(CHECKPOINT_SLED_KEY, bytes)?;
-        self.db.flush()?;
+        /// how indexed tapscripts are compared against the query
+        #[arg(long = "match", value_enum, default_value = "exact")]
+        match_mode: MatchMode,
+    },
+    /// check a witness, or a lone tapscript, for the indexed opcodes and print its disassembly.
+    /// Runs without a db or a node
+    Detect {
+        /// witness elements as hex, in order
+        #[arg(required_unless_present = "script")]
+        items: Vec<String>,
 
-        Ok(())
-    }
+        /// tapscript hex to check on its own instead of a witness
+        #[arg(long, conflicts_with = "items")]
+        script: Option<String>,
+    },
+    /// print statistics over the whole index
+    Stats,
+    /// print the size of the db on disk and how much it holds
+    #[command(name = "db_info", visible_alias = "db-info")]
+    DbInfo,
+    /// print the number of cat txs at every height of a range as json
+    Series {
+        /// first height of the range, defaults to the start block
+        #[arg(long)]
+        start: Option<u64>,
 
-    fn retrieve_check_point(&self) -> Result<u64> {
-        // if no checkpoint, start from start_block
-        if self.db.get(CHECKPOINT_SLED_KEY)?.is_none() {
-            return Ok(self.start_block);
-        }
+        /// height the range stops before, defaults to the end of the index
+        #[arg(long)]
+        finish: Option<u64>,
+    },
+    /// list the heights with the most cat txs
+    #[command(name = "top_blocks", visible_alias = "top-blocks")]
+    TopBlocks {
+        /// number of heights listed
+        #[arg(long, default_value = "10")]
+        limit: usize,
+    },
+    /// list the tapscripts first spent in a height range
+    #[command(name = "novel_scripts", visible_alias = "novel-scripts")]
+    NovelScripts {
+        /// first height of the range, defaults to the start block
+        #[arg(long)]
+        start: Option<u64>,
 
-        let checkpoint = self.db.get(CHECKPOINT_SLED_KEY)?.expect("checkpoint");
-        let height = ciborium::from_reader::<u64, _>(checkpoint.as_ref())?;
-        Ok(height)
+        /// height the range stops before, defaults to the end of the index
+        #[arg(long)]
+        end: Option<u64>,
+    },
+    /// convert a db from the legacy per height layout
+    Migrate,
+    /// write the index to a portable snapshot file
+    Export {
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// load a snapshot file into an empty db
+    Import {
+        #[arg(long = "in")]
+        input: PathBuf,
+    },
+    /// compare the stored block hashes against the node
+    Verify,
+    /// compare the txids indexed at every height against another db
+    Diff {
+        /// path of the db to compare against, opened with the same opcodes
+        #[arg(long)]
+        other: PathBuf,
+
+        /// list the txids only one of the dbs indexed
+        #[arg(long)]
+        verbose: bool,
+    },
+    /// list the txids indexed under more than one height
+    #[command(name = "check_duplicates", visible_alias = "check-duplicates")]
+    CheckDuplicates,
+    /// rewrite the checkpoint to match the heights actually indexed
+    #[command(name = "repair_checkpoint", visible_alias = "repair-checkpoint")]
+    RepairCheckpoint,
+    /// overwrite the checkpoint, indexing resumes from this height
+    #[command(name = "set_checkpoint", visible_alias = "set-checkpoint")]
+    SetCheckpoint {
+        height: u64,
+
+        /// confirm overwriting the checkpoint
+        #[arg(long)]
+        yes: bool,
+    },
+    /// drop indexed heights
+    Reset {
+        /// drop every indexed height from this one on and resume indexing there
+        #[arg(long, conflicts_with = "all")]
+        from: Option<u64>,
+
+        /// drop the whole index including the checkpoint
+        #[arg(long)]
+        all: bool,
+
+        /// confirm the reset
+        #[arg(long)]
+        yes: bool,
+    },
+    /// chart the index over the block range
+    Plot(PlotOptions),
+    /// write the indexed txs to files in the output directory
+    #[command(name = "generate_report")]
+    GenerateReport(ReportOptions),
+    /// serve the read only http api
+    Serve {
+        /// address the http api listens on
+        #[arg(long, default_value = "127.0.0.1:3000")]
+        listen: SocketAddr,
+    },
+}
+
+impl Command {
+    /// Whether the command talks to bitcoind, the others only read or write the db and run
+    /// without a node
+    fn needs_bitcoind(&self) -> bool {
+        match self {
+            Command::Index {
+                block_files: Some(_),
+                ..
+            } => false,
+            Command::Index { .. }
+            | Command::Tail { .. }
+            | Command::Checkpoint
+            | Command::Lag { .. }
+            | Command::Mempool { .. }
+            | Command::BenchDetector { .. }
+            | Command::Migrate
+            | Command::Verify
+            | Command::SetCheckpoint { .. }
+            | Command::Serve { .. } => true,
+            Command::TotalCatTxs
+            | Command::LastSync
+            | Command::GetTx { .. }
+            | Command::FindScript { .. }
+            | Command::Detect { .. }
+            | Command::Stats
+            | Command::DbInfo
+            | Command::TopBlocks { .. }
+            | Command::Series { .. }
+            | Command::NovelScripts { .. }
+            | Command::Export { .. }
+            | Command::Import { .. }
+            | Command::Reset { .. }
+            | Command::Diff { .. }
+            | Command::CheckDuplicates
+            | Command::RepairCheckpoint
+            | Command::Plot(_)
+            | Command::GenerateReport(_) => false,
+        }
     }
+}
 
-    fn insert_tx(&mut self, height: u64, tx: Transaction) -> Result<()> {
-        let mut set = {
-            if let Some(current_txs) = self.db.get(height.to_string())? {
-                let map = ciborium::from_reader::<HashSet<Transaction>, _>(current_txs.as_ref())?;
-                map
-            } else {
-                HashSet::new()
-            }
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    Text,
+    /// one json object per line
+    Json,
+}
+
+/// Contents of the `--config` file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    bitcoind_url: Option<String>,
+    bitcoind_port: Option<u16>,
+    bitcoind_username: Option<String>,
+    bitcoind_password: Option<String>,
+    bitcoind_password_file: Option<PathBuf>,
+    bitcoind_cookie: Option<PathBuf>,
+    start_block: Option<u64>,
+    db_path: Option<String>,
+}
+
+impl Args {
+    /// Fill in whatever wasn't given on the command line from the config file
+    fn merge_config(mut self) -> Result<Self> {
+        let Some(path) = &self.config else {
+            return Ok(self);
         };
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        let file: FileConfig = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse config file {}", path.display()))?;
 
-        set.insert(tx);
-        let mut bytes = Vec::new();
-        ciborium::into_writer(&set, &mut bytes)?;
-        self.db.insert(height.to_string(), bytes)?;
-        self.db.flush()?;
+        self.bitcoind_url = self.bitcoind_url.or(file.bitcoind_url);
+        self.bitcoind_port = self
+            .bitcoind_port
+            .or(file.bitcoind_port.map(|port| port.to_string()));
+        self.bitcoind_username = self.bitcoind_username.or(file.bitcoind_username);
+        // A password file on the command line beats a password in the config file
+        if self.bitcoind_password_file.is_none() {
+            self.bitcoind_password = self.bitcoind_password.or(file.bitcoind_password);
+        }
+        self.bitcoind_password_file = self.bitcoind_password_file.or(file.bitcoind_password_file);
+        self.bitcoind_cookie = self.bitcoind_cookie.or(file.bitcoind_cookie);
+        if self.start_block_hash.is_none() {
+            self.start_block = self.start_block.or(file.start_block);
+        }
+        self.db_path = self.db_path.or(file.db_path);
+        Ok(self)
+    }
 
-        Ok(())
+    /// Value of a setting that has no default and has to come from either a flag or the file
+    fn required<'a>(value: &'a Option<String>, name: &str) -> Result<&'a str> {
+        value.as_deref().ok_or_else(|| {
+            anyhow!(
+                "missing {}, pass --{} or set {} in the config file",
+                name.replace('_', " "),
+                name.replace('_', "-"),
+                name
+            )
+        })
     }
 
-    fn parse_block(&mut self, height: u64, block: Block) -> Result<()> {
-        info!("parsing block height: {}", height);
-        debug!("total txs in block: {}", block.txdata.len());
-        let mut cat_count = 0;
-        for tx in block.txdata.iter() {
-            for input in tx.input.iter() {
-                if witness_includes_cat(&input.witness) {
-                    // Double check that the prevout is a P2TR
-                    let prevout = self
-                        .bitcoind_rpc
-                        .get_raw_transaction(&input.previous_output.txid, None)?;
-                    let prev_output = prevout.output[input.previous_output.vout as usize].clone();
-                    let script_pubkey = prev_output.script_pubkey.clone();
-                    if script_pubkey.is_p2tr() {
-                        info!("found cat in witness for txid: {}", tx.compute_txid());
-                        let _ = self.insert_tx(height, tx.clone()).expect("to insert tx");
-                        cat_count += 1;
-                    }
-                }
-            }
-        }
-        info!("block height: {}, cat txs: {}", height, cat_count);
-        Ok(())
+    /// Resolve everything the indexer needs once the config file is merged in
+    fn config(&self) -> Result<Config> {
+        let offline = !self.command.needs_bitcoind();
+        let (bitcoind_urls, bitcoind_auth) = if offline {
+            (vec![], Auth::None)
+        } else {
+            (self.bitcoind_urls()?, self.auth()?)
+        };
+        Ok(Config {
+            bitcoind_urls,
+            bitcoind_auth,
+            network: self.network,
+            start_block: self.start_block,
+            start_block_hash: self.start_block_hash,
+            force: self.force,
+            db_path: match self.in_memory {
+                true => PathBuf::from(IN_MEMORY_DB_PATH),
+                false => PathBuf::from(self.db_path.as_deref().unwrap_or(DEFAULT_DB_PATH)),
+            },
+            offline,
+            confirmations: self.confirmations,
+            rpc_concurrency: self.rpc_concurrency,
+            rpc_max_retries: self.rpc_max_retries,
+            parse_threads: self.parse_threads,
+            opcodes: self.opcodes.clone(),
+            strict_prevout_check: self.strict_prevout_check,
+            with_annex: self.with_annex,
+            scan_all_witness_items: self.scan_all_witness_items,
+            output_dir: self.output_dir.clone(),
+            compress: self.compress,
+        })
     }
 
-    fn get_total_cat_txs(&self) -> Result<u64> {
-        let mut total_cats = 0;
-        let starting_height = self.start_block;
-        let tip = self.bitcoind_rpc.get_block_count()? - BLOCK_DEPTH;
-        for i in starting_height..tip {
-            if let Some(txs) = self.db.get(i.to_string())? {
-                let set = ciborium::from_reader::<HashSet<Transaction>, _>(txs.as_ref())?;
-                total_cats += set.len() as u64;
-            }
+    /// Every configured bitcoind endpoint as a url, hosts without their own port use
+    /// --bitcoind-port
+    fn bitcoind_urls(&self) -> Result<Vec<String>> {
+        let hosts = Args::required(&self.bitcoind_url, "bitcoind_url")?;
+        let urls = hosts
+            .split(',')
+            .map(str::trim)
+            .filter(|host| !host.is_empty())
+            .map(|host| match host {
+                host if host.contains(':') => Ok(format!("http://{}", host)),
+                host => Ok(format!(
+                    "http://{}:{}",
+                    host,
+                    Args::required(&self.bitcoind_port, "bitcoind_port")?
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if urls.is_empty() {
+            return Err(anyhow!("no bitcoind host in bitcoind url {:?}", hosts));
         }
-        Ok(total_cats)
+        Ok(urls)
     }
 
-    /// Return a vector of tuples of block height and total cat txs for that block
-    fn get_cats_in_range(&self, start: u64, finish: u64) -> Result<Vec<(i32, i32)>> {
-        let mut total_cats = vec![];
-        for i in start..finish {
-            if let Some(txs) = self.db.get(i.to_string())? {
-                let set = ciborium::from_reader::<HashSet<Transaction>, _>(txs.as_ref())?;
-                total_cats.push((i as i32, set.len() as i32));
-            } else {
-                total_cats.push((i as i32, 0));
-            }
+    /// The password given as a flag or in the config file, else read from the password file,
+    /// else taken from the BITCOIND_PASSWORD environment variable
+    fn password(&self) -> Result<Option<String>> {
+        if let Some(password) = &self.bitcoind_password {
+            return Ok(Some(password.clone()));
         }
-        Ok(total_cats)
+        if let Some(path) = &self.bitcoind_password_file {
+            let password = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read password file {}", path.display()))?;
+            return Ok(Some(password.trim_end_matches(['\r', '\n']).to_string()));
+        }
+        // A password left in the environment shouldn't clash with a cookie file
+        if self.bitcoind_username.is_none() {
+            return Ok(None);
+        }
+        Ok(std::env::var(BITCOIND_PASSWORD_ENV).ok())
     }
 
-    fn generate_cat_report(&self) -> Result<()> {
-        info!("generating report");
-        // One giant vec of TransactionExt for all blocks
-        let mut all_txs = vec![];
-        let checkpoint = self.retrieve_check_point()?;
-
-        // let start_block = self.start_block;
-        let start_block = checkpoint - 1000;
-        for i in start_block..checkpoint {
-            if let Some(txs) = self.db.get(i.to_string())? {
-                let set = ciborium::from_reader::<HashSet<Transaction>, _>(txs.as_ref())?;
-                for tx in set.iter() {
-                    let mut scripts_asm = vec![];
-                    let mut scripts_hex = vec![];
-                    for input in tx.input.iter() {
-                        // Some inputs will not include CAT but at least one will
-                        // lets include all of them
-                        let tapscript = Script::from_bytes(
-                            input.witness.nth(input.witness.len() - 2).expect("witness"),
-                        );
-                        if tapscript.to_asm_string().contains("OP_CAT") {
-                            scripts_asm.push(tapscript.to_asm_string());
-                            scripts_hex.push(tapscript.to_hex_string());
-                        }
-                    }
-                    all_txs.push(TransactionExt {
-                        height: i,
-                        scripts_asm,
-                        scripts_hex,
-                        size: tx.total_size() as u64,
-                        tx: tx.clone(),
-                    });
-                }
+    /// Exactly one of the cookie file or the username and password pair has to be configured
+    fn auth(&self) -> Result<Auth> {
+        match (
+            &self.bitcoind_cookie,
+            &self.bitcoind_username,
+            &self.password()?,
+        ) {
+            (Some(cookie), None, None) => Ok(Auth::CookieFile(cookie.clone())),
+            (None, Some(username), Some(password)) => {
+                Ok(Auth::UserPass(username.clone(), password.clone()))
+            }
+            (Some(_), _, _) => {
+                bail!("--bitcoind-cookie can't be combined with --bitcoind-username/--bitcoind-password")
+            }
+            (None, None, None) => bail!(
+                "no bitcoind auth configured, pass --bitcoind-cookie or --bitcoind-username and --bitcoind-password-file"
+            ),
+            (None, _, _) => {
+                bail!("--bitcoind-username and --bitcoind-password must be given together")
             }
         }
-
-        // write to a json file
-        let json = serde_json::to_string(&all_txs)?;
-        let file_name = format!("output/cat_txs.json");
-        std::fs::write(file_name.clone(), json)?;
-        info!("report generated to {}", file_name);
-
-        Ok(())
     }
+}
 
-    fn create_plots(&self) -> Result<()> {
-        let tip = self.bitcoind_rpc.get_block_count()? - BLOCK_DEPTH;
-        let height_range = (self.start_block as i32)..(tip as i32);
-        let total_cats = self.get_cats_in_range(self.start_block, tip)?;
-        let root = BitMapBackend::new("output/total_cat_txs.png", (1500, 800)).into_drawing_area();
-        root.fill(&WHITE)?;
-        let mut chart = ChartBuilder::on(&root)
-            .caption("CATS over time", ("sans-serif", 50).into_font())
-            .margin(10)
-            .x_label_area_size(30)
-            .y_label_area_size(40)
-            .build_cartesian_2d(height_range.clone(), 0..300)?;
-
-        chart
-            .configure_mesh()
-            .x_desc("block heights")
-            .y_desc("txs using CAT")
-            .draw()?;
-
-        chart
-            .draw_series(LineSeries::new(total_cats, &RED))?
-            .label("Txs using CAT")
-            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
-
-        chart
-            .configure_series_labels()
-            .background_style(&WHITE.mix(0.8))
-            .border_style(&BLACK)
-            .draw()?;
-
-        root.present()?;
-        Ok(())
+/// Print an error to stderr as a single line and exit instead of unwinding. Printed rather than
+/// logged, so it shows up with --log-level off or a --log-file too
+fn or_exit<T, E: Into<anyhow::Error>>(result: Result<T, E>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("error: {:#}", err.into());
+            std::process::exit(1);
+        }
     }
 }
 
-fn witness_includes_cat(witness: &Witness) -> bool {
-    // get the second to last element in the witness which should be the tapscript
-    // ignoring all annex things
-    if witness.len() <= 2 {
-        return false;
+/// Log to stderr, or append to --log-file, as text or one json object per line
+fn init_logging(args: &Args) -> Result<()> {
+    let mut builder = env_logger::builder();
+    // sled and the rpc client are too chatty below info
+    let dependency_level = args.log_level.min(LevelFilter::Info);
+    builder
+        .filter_level(args.log_level)
+        .filter_module("sled::", dependency_level)
+        .filter_module("bitcoincore_rpc::", dependency_level);
+    if let Some(path) = &args.log_file {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open log file {}", path.display()))?;
+        builder.target(env_logger::Target::Pipe(Box::new(file)));
+    }
+    if args.log_format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = json!({
+                "timestamp": buf.timestamp().to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
     }
+    builder.init();
 
-    let tapscript = Script::from_bytes(witness.nth(witness.len() - 2).expect("witness"));
-    // Is there a better way to do this?
-    // If we just iterate over the individual opcodes its possible but then we have to make sure
-    // we skip the data portion of any datapush opcodes -- seems more work than just checking for "CAT" str
-    tapscript.to_asm_string().contains("OP_CAT")
+    Ok(())
 }
 
 fn main() {
-    env_logger::builder()
-        .filter_level(log::LevelFilter::Info)
-        .filter_module("sled::", log::LevelFilter::Info)
-        .filter_module("bitcoincore_rpc::", log::LevelFilter::Info)
-        .init();
-
     let args = Args::parse();
-    let mut app = App::new(args.clone());
+    if let Err(err) = init_logging(&args) {
+        eprintln!("{:#}", err);
+        std::process::exit(1);
+    }
 
-    // Read the last argument as a command
-    let command = std::env::args().last().expect("need a command");
+    let args = or_exit(args.merge_config());
+    // The detector doesn't need the db, don't create one just to check a witness
+    if let Command::Detect { items, script } = &args.command {
+        let detections = match script {
+            Some(script) => vec![detect_script(
+                Script::from_bytes(&or_exit(parse_hex(script))),
+                &args.opcodes,
+            )],
+            None => {
+                let items: Vec<Vec<u8>> =
+                    items.iter().map(|item| or_exit(parse_hex(item))).collect();
+                detect_witness(
+                    &Witness::from_slice(&items),
+                    &args.opcodes,
+                    args.scan_all_witness_items,
+                )
+            }
+        };
+        if args.json {
+            println!(
+                "{}",
+                serde_json::to_string(&detections).expect("serialize detections")
+            );
+        } else {
+            if detections.is_empty() {
+                info!("no tapscript in the witness, --scan-all-witness-items checks every element");
+            }
+            for detection in detections.iter() {
+                let name = match detection.witness_index {
+                    Some(index) => format!("witness element {}", index),
+                    None => "script".to_string(),
+                };
+                if detection.opcodes.is_empty() {
+                    info!("{} uses none of the indexed opcodes", name);
+                } else {
+                    info!("{} uses {}", name, detection.opcodes.join(","));
+                }
+                info!("asm: {}", detection.asm);
+            }
+        }
+        return;
+    }
+
+    let app = or_exit(args.config().and_then(|config| Ok(App::new(config)?)));
 
-    match command.as_str() {
-        "start_index" => {
-            app.start_index().expect("start indexing");
+    match &args.command {
+        Command::Index {
+            options,
+            metrics_listen,
+            block_files,
+        } => {
+            let shutdown = app.shutdown_handle();
+            ctrlc::set_handler(move || {
+                info!("shutting down after the current block");
+                shutdown.store(true, Ordering::SeqCst);
+            })
+            .expect("install ctrl-c handler");
+            if let Some(listen) = *metrics_listen {
+                let metrics = app.metrics();
+                std::thread::spawn(move || {
+                    let runtime = tokio::runtime::Runtime::new().expect("start tokio runtime");
+                    if let Err(err) = runtime.block_on(server::serve_metrics(metrics, listen)) {
+                        error!("metrics exporter stopped: {:#}", err);
+                    }
+                });
+            }
+            let file_source = block_files
+                .as_deref()
+                .map(|path| or_exit(FileSource::open(path, args.network)));
+            let source: &dyn BlockSource = match &file_source {
+                Some(file_source) => file_source,
+                None => or_exit(app.block_source()),
+            };
+            or_exit(app.start_index(source, options));
         }
-        "get_checkpoint" => {
-            let checkpoint = app.retrieve_check_point().expect("get checkpoint");
-            let tip = app.bitcoind_rpc.get_block_count().expect("get block count");
-            info!("checkpoint: {}", checkpoint);
-            info!("tip: {}", tip);
+        Command::Tail { poll_interval } => {
+            let shutdown = app.shutdown_handle();
+            ctrlc::set_handler(move || {
+                info!("shutting down after the current block");
+                shutdown.store(true, Ordering::SeqCst);
+            })
+            .expect("install ctrl-c handler");
+            let options = IndexOptions {
+                follow: true,
+                poll_interval: *poll_interval,
+                ..Default::default()
+            };
+            or_exit(app.start_index_with(
+                or_exit(app.block_source()),
+                &options,
+                |height, tx_exts| {
+                    for tx_ext in tx_exts {
+                        let txid = tx_ext.tx.compute_txid();
+                        if args.json {
+                            println!(
+                                "{}",
+                                json!({ "height": height, "txid": txid, "opcodes": tx_ext.opcodes })
+                            );
+                        } else {
+                            println!("{} {} {}", height, txid, tx_ext.opcodes.join(","));
+                        }
+                    }
+                },
+            ));
+        }
+        Command::Checkpoint => {
+            let checkpoint = or_exit(app.retrieve_check_point());
+            let tip = or_exit(app.tip_height());
+            if args.json {
+                println!("{}", json!({ "checkpoint": checkpoint, "tip": tip }));
+            } else {
+                info!("checkpoint: {}", checkpoint);
+                info!("tip: {}", tip);
+            }
+        }
+        Command::DbInfo => {
+            let db_info = or_exit(app.db_info());
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&db_info).expect("serialize db info")
+                );
+            } else {
+                info!("size on disk: {} bytes", db_info.size_on_disk);
+                info!("indexed heights: {}", db_info.heights);
+                info!("stored txs: {}", db_info.txs);
+                match db_info.checkpoint {
+                    Some(checkpoint) => info!("checkpoint: {}", checkpoint),
+                    None => info!("checkpoint: none, nothing indexed yet"),
+                }
+                info!("start block: {}", db_info.start_block);
+                for (name, tree) in db_info.trees.iter() {
+                    info!(
+                        "tree {}: {} entries, {} bytes",
+                        name, tree.entries, tree.bytes
+                    );
+                }
+            }
+        }
+        Command::LastSync => match or_exit(app.last_sync()) {
+            Some(sync) if args.json => {
+                println!("{}", serde_json::to_string(&sync).expect("serialize sync"));
+            }
+            Some(sync) => {
+                let time = |timestamp| {
+                    DateTime::from_timestamp(timestamp, 0)
+                        .map(|time| time.to_rfc3339())
+                        .unwrap_or_default()
+                };
+                info!("started: {}", time(sync.started_at));
+                info!("finished: {}", time(sync.finished_at));
+                info!("heights: {}..{}", sync.start_height, sync.end_height);
+                info!(
+                    "indexed {} blocks in {:.1} seconds, {:.2} blocks/sec",
+                    sync.blocks, sync.seconds, sync.blocks_per_sec
+                );
+                info!("found {} cat txs", sync.cat_txs);
+            }
+            None => info!("nothing has been indexed yet"),
+        },
+        Command::Lag { max_lag } => {
+            let lag = or_exit(app.lag());
+            if args.json {
+                println!("{}", serde_json::to_string(&lag).expect("serialize lag"));
+            } else {
+                info!("tip: {}", lag.tip);
+                info!("target: {}", lag.target);
+                info!("checkpoint: {}", lag.checkpoint);
+                info!("behind: {}", lag.behind);
+            }
+            if let Some(max_lag) = *max_lag {
+                if lag.behind > max_lag {
+                    error!(
+                        "{} blocks behind, more than --max-lag {}",
+                        lag.behind, max_lag
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::TotalCatTxs => {
+            let total_cats = or_exit(app.get_total_cat_txs());
+            if args.json {
+                println!("{}", json!({ "total_cat_txs": total_cats }));
+            } else {
+                info!("total cat txs: {}", total_cats);
+            }
         }
-        "get_total_cat_txs" => {
-            let total_cats = app.get_total_cat_txs().expect("get total cat txs");
-            info!("total cat txs: {}", total_cats);
+        Command::BenchDetector { range } => {
+            let bench = or_exit(app.bench_detector(range.clone()));
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&bench).expect("serialize bench")
+                );
+            } else {
+                info!(
+                    "scanned {} txs in {} blocks, {} matched",
+                    bench.txs, bench.blocks, bench.matches
+                );
+                info!(
+                    "{:.3} seconds, {:.0} txs/sec, {:.2} MB/sec of witness data",
+                    bench.seconds, bench.txs_per_sec, bench.mb_per_sec
+                );
+            }
+        }
+        Command::Mempool {
+            follow,
+            poll_interval,
+        } => loop {
+            let matches = or_exit(app.scan_mempool());
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&matches).expect("serialize mempool txs")
+                );
+            } else {
+                for tx in matches.iter() {
+                    info!("unconfirmed {} uses {}", tx.txid, tx.opcodes.join(","));
+                }
+                info!("{} unconfirmed txs match", matches.len());
+            }
+            if !follow {
+                break;
+            }
+            std::thread::sleep(Duration::from_secs(*poll_interval));
+        },
+        Command::GetTx { txid, unique } => {
+            let tx_exts = or_exit(app.find_txs(txid, *unique));
+            if tx_exts.is_empty() {
+                info!("tx {} not found, it is not indexed", txid);
+            }
+            for tx_ext in tx_exts.iter() {
+                info!("txid: {}", tx_ext.tx.compute_txid());
+                info!("height: {}", tx_ext.height);
+                info!("matched opcodes: {}", tx_ext.opcodes.join(","));
+                let matched_inputs: Vec<String> =
+                    tx_ext.matched_inputs().iter().map(u32::to_string).collect();
+                info!("matched inputs: {}", matched_inputs.join(","));
+                for input in tx_ext.inputs.iter() {
+                    info!("input {} tapscript: {}", input.input, input.asm);
+                }
+                info!("raw tx: {}", serialize_hex(&tx_ext.tx));
+            }
+        }
+        Command::Detect { .. } => unreachable!("detect runs before the db is opened"),
+        Command::Stats => {
+            let stats = or_exit(app.compute_stats());
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&stats).expect("serialize stats")
+                );
+            } else {
+                stats.log_summary();
+            }
+        }
+        Command::Series { start, finish } => {
+            let start = start.unwrap_or(app.start_block());
+            let finish = match finish {
+                Some(finish) => *finish,
+                None => or_exit(app.indexed_end()),
+            };
+            let series = or_exit(app.series(start, finish));
+            println!(
+                "{}",
+                serde_json::to_string(&series).expect("serialize series")
+            );
+        }
+        Command::TopBlocks { limit } => {
+            let blocks = or_exit(app.top_blocks(*limit));
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&blocks).expect("serialize blocks")
+                );
+            } else {
+                for block in blocks.iter() {
+                    info!("height {}: {} cat txs", block.height, block.count);
+                }
+            }
+        }
+        Command::FindScript { query, match_mode } => {
+            let hits = or_exit(app.find_script(query, *match_mode));
+            if args.json {
+                println!("{}", serde_json::to_string(&hits).expect("serialize hits"));
+            } else {
+                for hit in hits.iter() {
+                    info!("{} {} inputs {:?}", hit.height, hit.txid, hit.inputs);
+                }
+                info!("{} txs spend a matching tapscript", hits.len());
+            }
+        }
+        Command::NovelScripts { start, end } => {
+            let start = start.unwrap_or(app.start_block());
+            let end = match end {
+                Some(end) => *end,
+                None => or_exit(app.indexed_end()),
+            };
+            let scripts = or_exit(app.novel_scripts(start..end));
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&scripts).expect("serialize scripts")
+                );
+            } else {
+                for script in scripts.iter() {
+                    info!("{} {}", script.height, script.tapscript_hex);
+                }
+                info!(
+                    "{} tapscripts first seen in {}..{}",
+                    scripts.len(),
+                    start,
+                    end
+                );
+            }
+        }
+        Command::Migrate => {
+            let migrated = or_exit(app.migrate_storage());
+            info!("migrated {} heights to the new storage layout", migrated);
+        }
+        Command::Export { out } => {
+            let exported = or_exit(app.export_snapshot(out));
+            info!("exported {} heights to {}", exported, out.display());
+        }
+        Command::Import { input } => {
+            let imported = or_exit(app.import_snapshot(input));
+            info!("imported {} heights from {}", imported, input.display());
+        }
+        Command::Verify => {
+            let summary = or_exit(app.verify());
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&summary).expect("serialize summary")
+                );
+            } else {
+                info!(
+                    "matched: {}, mismatched: {}, missing: {}",
+                    summary.matched,
+                    summary.mismatched.len(),
+                    summary.missing
+                );
+            }
+        }
+        Command::Diff { other, verbose } => {
+            // Opened read only, its start block is whatever it was indexed from
+            let other = or_exit(args.config().and_then(|config| {
+                // Opening a missing path would create an empty db
+                if !other.exists() {
+                    bail!("no db at {}", other.display());
+                }
+                // sled would fail to lock the db a second time
+                if config.db_path.canonicalize().ok() == Some(other.canonicalize()?) {
+                    bail!("--other {} is the db being compared", other.display());
+                }
+                Ok(IndexView::open(other, &config.opcodes, config.network)?)
+            }));
+            let mut diff = or_exit(app.diff(&other));
+            if !verbose {
+                diff.details.clear();
+            }
+            if args.json {
+                println!("{}", serde_json::to_string(&diff).expect("serialize diff"));
+            } else {
+                info!(
+                    "compared heights {}..{}, {} differ, txids only here: {}, only in other: {}",
+                    diff.start,
+                    diff.end,
+                    diff.differing.len(),
+                    diff.only_here,
+                    diff.only_other
+                );
+                for height in diff.details.iter() {
+                    for txid in height.only_here.iter() {
+                        info!("{} only here: {}", height.height, txid);
+                    }
+                    for txid in height.only_other.iter() {
+                        info!("{} only in other: {}", height.height, txid);
+                    }
+                }
+                if !verbose && !diff.differing.is_empty() {
+                    info!("differing heights: {:?}", diff.differing);
+                }
+            }
+        }
+        Command::CheckDuplicates => {
+            let duplicates = or_exit(app.check_duplicates());
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&duplicates).expect("serialize duplicates")
+                );
+            } else {
+                for duplicate in duplicates.iter() {
+                    warn!(
+                        "{} is indexed at heights {:?}",
+                        duplicate.txid, duplicate.heights
+                    );
+                }
+                info!("{} txids indexed more than once", duplicates.len());
+            }
+        }
+        Command::RepairCheckpoint => {
+            let repair = or_exit(app.repair_check_point());
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&repair).expect("serialize repair")
+                );
+            } else {
+                match repair.previous {
+                    Some(previous) => {
+                        info!("checkpoint {} rewritten to {}", previous, repair.checkpoint)
+                    }
+                    None => info!("checkpoint restored to {}", repair.checkpoint),
+                }
+                info!("{} gaps in the indexed heights", repair.gaps.len());
+            }
+        }
+        Command::SetCheckpoint { height, yes } => {
+            if !yes {
+                error!("set_checkpoint overwrites the checkpoint, pass --yes to confirm");
+                std::process::exit(1);
+            }
+            or_exit(app.set_check_point(*height));
+            info!("checkpoint set to {}, indexing resumes there", height);
+        }
+        Command::Reset { from, all, yes } => {
+            if !yes {
+                error!("reset deletes indexed data, pass --yes to confirm");
+                std::process::exit(1);
+            }
+            match (*from, *all) {
+                (Some(from), false) => {
+                    or_exit(app.reset_from(from));
+                    info!("dropped heights from {}, indexing resumes there", from);
+                }
+                (None, true) => {
+                    or_exit(app.reset_all());
+                    info!("dropped the whole index");
+                }
+                _ => {
+                    error!("reset needs either --from <height> or --all");
+                    std::process::exit(1);
+                }
+            }
         }
-        "plot" => app.create_plots().expect("create plots"),
-        "generate_report" => app.generate_cat_report().expect("generate report"),
-        _ => {
-            info!("No command found");
+        Command::Plot(options) => or_exit(app.create_plots(options)),
+        Command::GenerateReport(options) => or_exit(app.generate_cat_report(options)),
+        Command::Serve { listen } => {
+            let runtime = tokio::runtime::Runtime::new().expect("start tokio runtime");
+            or_exit(runtime.block_on(server::serve(Arc::new(app), *listen)));
         }
     }
 }
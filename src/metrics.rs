@@ -0,0 +1,57 @@
+//! Sync health gauges, rendered in the prometheus text format
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub checkpoint_height: AtomicU64,
+    pub tip_height: AtomicU64,
+    pub total_cat_txs: AtomicU64,
+    /// blocks indexed by this process
+    pub blocks_indexed_total: AtomicU64,
+}
+
+impl Metrics {
+    pub fn render(&self) -> String {
+        let checkpoint = self.checkpoint_height.load(Ordering::Relaxed);
+        let tip = self.tip_height.load(Ordering::Relaxed);
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        metric(
+            "felix_checkpoint_height",
+            "gauge",
+            "Next height to be indexed",
+            checkpoint,
+        );
+        metric(
+            "felix_tip_height",
+            "gauge",
+            "Block count reported by bitcoind",
+            tip,
+        );
+        metric(
+            "felix_blocks_behind",
+            "gauge",
+            "Blocks between the checkpoint and the tip",
+            tip.saturating_sub(checkpoint),
+        );
+        metric(
+            "felix_total_cat_txs",
+            "gauge",
+            "Transactions in the index",
+            self.total_cat_txs.load(Ordering::Relaxed),
+        );
+        metric(
+            "felix_blocks_indexed_total",
+            "counter",
+            "Blocks indexed since the process started",
+            self.blocks_indexed_total.load(Ordering::Relaxed),
+        );
+        out
+    }
+}
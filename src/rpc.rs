@@ -0,0 +1,136 @@
+//! bitcoind client failing over between several endpoints and retrying transient errors
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use bitcoincore_rpc::jsonrpc;
+use bitcoincore_rpc::{Auth, Client, Error, RpcApi};
+use log::{debug, warn};
+use serde_json::Value;
+
+/// Sends every request to the current endpoint and moves on to the next one when it can't be
+/// reached. With a single endpoint it behaves exactly like `Client`
+pub struct FailoverRpc {
+    endpoints: Vec<(String, Client)>,
+    /// index of the endpoint requests go to first
+    current: AtomicUsize,
+    /// how often `retry` repeats a request that failed with a transient error
+    max_retries: u32,
+}
+
+/// Delay before the first retry, doubled on every following one
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+/// bitcoind is still loading the block index or verifying blocks
+const RPC_IN_WARMUP: i32 = -28;
+/// bitcoind doesn't know the requested tx, or block, or address
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+impl FailoverRpc {
+    pub fn new(urls: &[String], auth: Auth, max_retries: u32) -> Result<Self, Error> {
+        if urls.is_empty() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no bitcoind url given",
+            )));
+        }
+        let endpoints = urls
+            .iter()
+            .map(|url| Ok((url.clone(), Client::new(url, auth.clone())?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self {
+            endpoints,
+            current: AtomicUsize::new(0),
+            max_retries,
+        })
+    }
+
+    /// Run `request`, repeating it with exponential backoff while it fails with a transient
+    /// error. Other errors, like a block that doesn't exist, are returned right away
+    pub fn retry<T>(&self, mut request: impl FnMut(&Self) -> Result<T, Error>) -> Result<T, Error> {
+        let mut delay = RETRY_BASE_DELAY;
+        let mut retries = 0;
+        loop {
+            match request(self) {
+                Err(err) if is_transient_error(&err) && retries < self.max_retries => {
+                    retries += 1;
+                    warn!(
+                        "bitcoind request failed ({}), retry {}/{} in {:?}",
+                        err, retries, self.max_retries, delay
+                    );
+                    std::thread::sleep(delay);
+                    delay = (delay * 2).min(RETRY_MAX_DELAY);
+                }
+                result => {
+                    if retries > 0 && result.is_ok() {
+                        debug!("bitcoind request succeeded after {} retries", retries);
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+/// Errors of the transport itself, like a refused connection or a timeout, rather than bitcoind
+/// answering with an error
+fn is_connection_error(err: &Error) -> bool {
+    matches!(err, Error::JsonRpc(jsonrpc::Error::Transport(_)))
+}
+
+/// Errors worth trying again: the node being unreachable or still starting up
+fn is_transient_error(err: &Error) -> bool {
+    match err {
+        Error::JsonRpc(jsonrpc::Error::Rpc(err)) => err.code == RPC_IN_WARMUP,
+        Error::Io(_) => true,
+        err => is_connection_error(err),
+    }
+}
+
+/// bitcoind answering `getrawtransaction` without the tx, which happens for any confirmed tx
+/// with no unspent outputs left when it runs without -txindex
+pub fn is_tx_not_found(err: &Error) -> bool {
+    matches!(err, Error::JsonRpc(jsonrpc::Error::Rpc(err)) if err.code == RPC_INVALID_ADDRESS_OR_KEY)
+}
+
+impl RpcApi for FailoverRpc {
+    fn call<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        cmd: &str,
+        args: &[Value],
+    ) -> Result<T, Error> {
+        let first = self.current.load(Ordering::Relaxed);
+        let mut result = Err(Error::UnexpectedStructure);
+        for attempt in 0..self.endpoints.len() {
+            let index = (first + attempt) % self.endpoints.len();
+            let (url, client) = &self.endpoints[index];
+            result = client.call(cmd, args);
+            match &result {
+                Err(err) if is_connection_error(err) && self.endpoints.len() > 1 => {
+                    let next = (index + 1) % self.endpoints.len();
+                    warn!(
+                        "bitcoind at {} unreachable ({}), failing over to {}",
+                        url, err, self.endpoints[next].0
+                    );
+                    self.current.store(next, Ordering::Relaxed);
+                }
+                _ => return result,
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_no_endpoints() {
+        let err = FailoverRpc::new(&[], Auth::None, 0).err().unwrap();
+        assert!(err.to_string().contains("no bitcoind url given"));
+    }
+}
@@ -0,0 +1,234 @@
+//! Read only HTTP API over the index, started by the `serve` command
+
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use log::info;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::metrics::Metrics;
+use crate::{App, BlockCount, FelixError};
+
+/// Most heights a single range query may span, each one is a db lookup and an entry in the
+/// response
+const MAX_RANGE_BLOCKS: u64 = 100_000;
+
+/// Wraps any error coming out of the app so it can be returned as a 500, or a rejected request as
+/// a 400
+struct ApiError(StatusCode, anyhow::Error);
+
+impl ApiError {
+    fn bad_request(message: String) -> Self {
+        Self(StatusCode::BAD_REQUEST, anyhow::Error::msg(message))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(json!({ "error": format!("{:#}", self.1) }));
+        (self.0, body).into_response()
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for ApiError {
+    fn from(err: E) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, err.into())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RangeParams {
+    start: u64,
+    finish: u64,
+}
+
+impl RangeParams {
+    /// The heights asked for, unless they span more than `MAX_RANGE_BLOCKS`
+    fn heights(&self) -> Result<Range<u64>, ApiError> {
+        if self.finish.saturating_sub(self.start) > MAX_RANGE_BLOCKS {
+            return Err(ApiError::bad_request(format!(
+                "range {}..{} spans more than {} blocks",
+                self.start, self.finish, MAX_RANGE_BLOCKS
+            )));
+        }
+        Ok(self.start..self.finish)
+    }
+}
+
+pub fn router(app: Arc<App>) -> Router {
+    Router::new()
+        .route("/cats/total", get(total_cats))
+        .route("/cats/range", get(cats_in_range))
+        .route("/cats/series", get(series))
+        .route("/checkpoint", get(checkpoint))
+        .route("/metrics", get(metrics))
+        .with_state(app)
+}
+
+/// Standalone exporter for the gauges of an indexing process
+pub async fn serve_metrics(metrics: Arc<Metrics>, listen: SocketAddr) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    info!("serving metrics on: {}", listener.local_addr()?);
+    let router = Router::new()
+        .route("/metrics", get(render_metrics))
+        .with_state(metrics);
+    axum::serve(listener, router).await?;
+
+    Ok(())
+}
+
+pub async fn serve(app: Arc<App>, listen: SocketAddr) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    info!("serving http api on: {}", listener.local_addr()?);
+    axum::serve(listener, router(app)).await?;
+
+    Ok(())
+}
+
+/// Sled and the rpc client are blocking so keep them off the async workers
+async fn blocking<T, F>(app: Arc<App>, f: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&App) -> Result<T, FelixError> + Send + 'static,
+{
+    Ok(tokio::task::spawn_blocking(move || f(&app)).await??)
+}
+
+async fn total_cats(State(app): State<Arc<App>>) -> Result<Json<Value>, ApiError> {
+    let total_cats = blocking(app, |app| app.get_total_cat_txs()).await?;
+    Ok(Json(json!({ "total_cat_txs": total_cats })))
+}
+
+async fn cats_in_range(
+    State(app): State<Arc<App>>,
+    Query(params): Query<RangeParams>,
+) -> Result<Json<Vec<(u64, u64)>>, ApiError> {
+    let heights = params.heights()?;
+    let cats = blocking(app, move |app| {
+        app.get_cats_in_range(heights.start, heights.end)
+    })
+    .await?;
+    Ok(Json(cats))
+}
+
+async fn series(
+    State(app): State<Arc<App>>,
+    Query(params): Query<RangeParams>,
+) -> Result<Json<Vec<BlockCount>>, ApiError> {
+    let heights = params.heights()?;
+    let series = blocking(app, move |app| app.series(heights.start, heights.end)).await?;
+    Ok(Json(series))
+}
+
+async fn checkpoint(State(app): State<Arc<App>>) -> Result<Json<Value>, ApiError> {
+    let checkpoint = blocking(app, |app| app.retrieve_check_point()).await?;
+    Ok(Json(json!({ "checkpoint": checkpoint })))
+}
+
+async fn metrics(State(app): State<Arc<App>>) -> Result<Response, ApiError> {
+    let metrics = blocking(app, |app| {
+        app.refresh_metrics()?;
+        Ok(app.metrics.clone())
+    })
+    .await?;
+    Ok(render_metrics(State(metrics)).await)
+}
+
+async fn render_metrics(State(metrics): State<Arc<Metrics>>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::MemorySource;
+    use crate::test_util::{cat_spend, chain, test_app};
+    use crate::tests::index;
+
+    /// Serve the api over `app` on an ephemeral port, returning its base url
+    fn spawn_server(app: App) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(Arc::new(app))).await });
+        url
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_index() {
+        let app = test_app(0);
+        let blocks = chain(
+            vec![
+                vec![cat_spend(1)],
+                vec![],
+                vec![cat_spend(2), cat_spend(3)],
+                vec![],
+            ],
+            0,
+        );
+        index(&app, &MemorySource::new(0, blocks));
+
+        let url = spawn_server(app);
+        let get = |path: &str| {
+            let url = format!("{}{}", url, path);
+            async move {
+                reqwest::get(url)
+                    .await
+                    .unwrap()
+                    .error_for_status()
+                    .unwrap()
+                    .json::<Value>()
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(get("/cats/total").await, json!({ "total_cat_txs": 3 }));
+        assert_eq!(get("/checkpoint").await, json!({ "checkpoint": 3 }));
+        assert_eq!(
+            get("/cats/range?start=0&finish=3").await,
+            json!([[0, 1], [1, 0], [2, 2]])
+        );
+        assert_eq!(
+            get("/cats/series?start=1&finish=3").await,
+            json!([{ "height": 1, "count": 0 }, { "height": 2, "count": 2 }])
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_oversized_ranges() {
+        let url = spawn_server(test_app(0));
+
+        let response = reqwest::get(format!("{}/cats/range?start=0&finish={}", url, u64::MAX))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.json::<Value>().await.unwrap();
+        assert!(body["error"].as_str().unwrap().contains("spans more than"));
+        let response = reqwest::get(format!("{}/cats/series?start=1&finish={}", url, u64::MAX))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // right at the limit is fine, heights past the index just count 0
+        let response = reqwest::get(format!(
+            "{}/cats/range?start=0&finish={}",
+            url, MAX_RANGE_BLOCKS
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
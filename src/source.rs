@@ -0,0 +1,397 @@
+//! Where indexing gets its blocks from: bitcoind, or the raw block files of a node's data dir
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use bitcoin::block::Header;
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, BlockHash};
+use bitcoincore_rpc::RpcApi;
+use log::info;
+
+use crate::error::{FelixError, Result};
+use crate::rpc::FailoverRpc;
+use crate::Network;
+
+/// Bytes of the network magic and the block size preceding every block in a block file
+const RECORD_HEADER_SIZE: usize = 8;
+const BLOCK_HEADER_SIZE: usize = 80;
+/// Obfuscation key bitcoind applies to the block files since v28, next to them in the blocks dir
+const XOR_KEY_FILE: &str = "xor.dat";
+
+/// The blocks of the best chain, by height
+pub trait BlockSource: Sync {
+    /// Height of the best block
+    fn tip(&self) -> Result<u64>;
+
+    fn block_at(&self, height: u64) -> Result<(BlockHash, Block)>;
+
+    /// Hash of the block at `height`, without fetching the whole block where the source can
+    fn hash_at(&self, height: u64) -> Result<BlockHash> {
+        Ok(self.block_at(height)?.0)
+    }
+
+    /// Header of the block at `height`
+    fn header_at(&self, height: u64) -> Result<Header> {
+        Ok(self.block_at(height)?.1.header)
+    }
+
+    /// Lowest height the source still has the block of, None when it has every block
+    fn prune_height(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+}
+
+impl BlockSource for FailoverRpc {
+    fn tip(&self) -> Result<u64> {
+        Ok(self.retry(|rpc| rpc.get_block_count())?)
+    }
+
+    fn block_at(&self, height: u64) -> Result<(BlockHash, Block)> {
+        let hash = self.hash_at(height)?;
+        Ok((hash, self.retry(|rpc| rpc.get_block(&hash))?))
+    }
+
+    fn hash_at(&self, height: u64) -> Result<BlockHash> {
+        Ok(self.retry(|rpc| rpc.get_block_hash(height))?)
+    }
+
+    fn header_at(&self, height: u64) -> Result<Header> {
+        let hash = self.hash_at(height)?;
+        Ok(self.retry(|rpc| rpc.get_block_header(&hash))?)
+    }
+
+    fn prune_height(&self) -> Result<Option<u64>> {
+        let blockchain_info =
+            self.retry(|rpc| rpc.call::<serde_json::Value>("getblockchaininfo", &[]))?;
+        if !blockchain_info["pruned"].as_bool().unwrap_or_default() {
+            return Ok(None);
+        }
+        Ok(blockchain_info["pruneheight"].as_u64())
+    }
+}
+
+/// Where a block sits in the block files
+#[derive(Debug, Clone, Copy)]
+struct BlockLocation {
+    hash: BlockHash,
+    file: usize,
+    /// offset of the serialized block, past its magic and size
+    offset: u64,
+    size: u32,
+}
+
+/// Blocks read straight from bitcoind's blk*.dat files, or from a single file in the same
+/// format like a linearized dump. The files hold blocks in the order they were received,
+/// including stale ones, so opening them reads every header to link up the best chain
+pub struct FileSource {
+    files: Vec<PathBuf>,
+    xor_key: Option<[u8; 8]>,
+    /// height of the first block of `chain`
+    base: u64,
+    chain: Vec<BlockLocation>,
+}
+
+impl FileSource {
+    /// Open a blocks dir, reading its blk*.dat files in order, or a single block file
+    pub fn open(path: &Path, network: Network) -> Result<Self> {
+        let (files, xor_key) = if path.is_dir() {
+            let mut files = vec![];
+            for entry in std::fs::read_dir(path)? {
+                let file = entry?.path();
+                let name = file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if name.starts_with("blk") && name.ends_with(".dat") {
+                    files.push(file);
+                }
+            }
+            files.sort();
+            (files, read_xor_key(&path.join(XOR_KEY_FILE))?)
+        } else {
+            let xor_key = match path.parent() {
+                Some(dir) => read_xor_key(&dir.join(XOR_KEY_FILE))?,
+                None => None,
+            };
+            (vec![path.to_path_buf()], xor_key)
+        };
+        if files.is_empty() {
+            return Err(FelixError::BlockFile(format!(
+                "no blk*.dat files in {}",
+                path.display()
+            )));
+        }
+
+        let magic = network_magic(network);
+        // every block by hash, with the hash of the block it builds on
+        let mut blocks: HashMap<BlockHash, (BlockHash, BlockLocation)> = HashMap::new();
+        for (index, file) in files.iter().enumerate() {
+            // Only the record and block headers are read, the transactions are seeked past
+            let mut reader = BufReader::new(File::open(file)?);
+            let len = reader.get_ref().metadata()?.len();
+            let mut pos = 0;
+            while pos + RECORD_HEADER_SIZE as u64 <= len {
+                let mut record = [0; RECORD_HEADER_SIZE];
+                reader.read_exact(&mut record)?;
+                if let Some(key) = &xor_key {
+                    unxor(&mut record, key, pos);
+                }
+                // bitcoind preallocates block files, the rest is zeroes
+                if record[..4] == [0; 4] {
+                    break;
+                }
+                if record[..4] != magic {
+                    return Err(FelixError::BlockFile(format!(
+                        "unexpected magic at offset {} of {}, wrong --network?",
+                        pos,
+                        file.display()
+                    )));
+                }
+                let size = u32::from_le_bytes(record[4..].try_into()?);
+                let offset = pos + RECORD_HEADER_SIZE as u64;
+                if (size as usize) < BLOCK_HEADER_SIZE || offset + size as u64 > len {
+                    return Err(FelixError::BlockFile(format!(
+                        "truncated block at offset {} of {}",
+                        pos,
+                        file.display()
+                    )));
+                }
+                let mut header = [0; BLOCK_HEADER_SIZE];
+                reader.read_exact(&mut header)?;
+                if let Some(key) = &xor_key {
+                    unxor(&mut header, key, offset);
+                }
+                let header: Header = bitcoin::consensus::deserialize(&header).map_err(|err| {
+                    FelixError::BlockFile(format!(
+                        "invalid block header at offset {} of {}: {}",
+                        pos,
+                        file.display(),
+                        err
+                    ))
+                })?;
+                let location = BlockLocation {
+                    hash: header.block_hash(),
+                    file: index,
+                    offset,
+                    size,
+                };
+                blocks.insert(location.hash, (header.prev_blockhash, location));
+                reader.seek_relative(size as i64 - BLOCK_HEADER_SIZE as i64)?;
+                pos = offset + size as u64;
+            }
+        }
+        info!(
+            "read {} block headers from {} files",
+            blocks.len(),
+            files.len()
+        );
+
+        let mut source = Self {
+            files,
+            xor_key,
+            base: 0,
+            chain: vec![],
+        };
+        source.link_best_chain(&blocks)?;
+        Ok(source)
+    }
+
+    /// Find the longest chain through `blocks`. Its first block is either the genesis block or
+    /// gets its height from the BIP34 height in its coinbase
+    fn link_best_chain(
+        &mut self,
+        blocks: &HashMap<BlockHash, (BlockHash, BlockLocation)>,
+    ) -> Result<()> {
+        // number of ancestors of every block within the files
+        let mut depths: HashMap<BlockHash, u64> = HashMap::new();
+        for hash in blocks.keys() {
+            let mut path = vec![];
+            let mut current = *hash;
+            // walk back to a block of known depth, or to the first block of its chain
+            let mut depth = loop {
+                if let Some(depth) = depths.get(&current) {
+                    break depth + 1;
+                }
+                path.push(current);
+                let prev = blocks[&current].0;
+                if !blocks.contains_key(&prev) {
+                    break 0;
+                }
+                current = prev;
+            };
+            for hash in path.into_iter().rev() {
+                depths.insert(hash, depth);
+                depth += 1;
+            }
+        }
+        let Some((tip, _)) = depths.iter().max_by_key(|(hash, depth)| (**depth, **hash)) else {
+            return Err(FelixError::BlockFile("no blocks in the block files".into()));
+        };
+
+        let mut chain = vec![];
+        let mut current = *tip;
+        while let Some((prev, location)) = blocks.get(&current) {
+            chain.push(*location);
+            current = *prev;
+        }
+        chain.reverse();
+        let root = chain[0];
+        self.base = if blocks[&root.hash].0 == BlockHash::all_zeros() {
+            0
+        } else {
+            self.read_block(&root)?
+                .bip34_block_height()
+                .map_err(|err| {
+                    FelixError::BlockFile(format!(
+                        "can't tell the height of the first block {}: {}",
+                        root.hash, err
+                    ))
+                })?
+        };
+        self.chain = chain;
+        info!(
+            "block files hold heights {}..={}",
+            self.base,
+            self.base + self.chain.len() as u64 - 1
+        );
+
+        Ok(())
+    }
+
+    fn location(&self, height: u64) -> Result<&BlockLocation> {
+        height
+            .checked_sub(self.base)
+            .and_then(|index| self.chain.get(index as usize))
+            .ok_or_else(|| {
+                FelixError::BlockFile(format!(
+                    "height {} isn't in the block files, they hold {}..={}",
+                    height,
+                    self.base,
+                    self.base + self.chain.len() as u64 - 1
+                ))
+            })
+    }
+
+    fn read_block(&self, location: &BlockLocation) -> Result<Block> {
+        let path = &self.files[location.file];
+        let bytes = self.read_bytes(location, location.size as usize)?;
+        bitcoin::consensus::deserialize(&bytes).map_err(|err| {
+            FelixError::BlockFile(format!(
+                "invalid block {} in {}: {}",
+                location.hash,
+                path.display(),
+                err
+            ))
+        })
+    }
+
+    /// The first `len` bytes of the block at `location`
+    fn read_bytes(&self, location: &BlockLocation, len: usize) -> Result<Vec<u8>> {
+        let mut file = File::open(&self.files[location.file])?;
+        file.seek(SeekFrom::Start(location.offset))?;
+        let mut bytes = vec![0; len];
+        file.read_exact(&mut bytes)?;
+        if let Some(key) = &self.xor_key {
+            unxor(&mut bytes, key, location.offset);
+        }
+        Ok(bytes)
+    }
+}
+
+impl BlockSource for FileSource {
+    fn tip(&self) -> Result<u64> {
+        Ok(self.base + self.chain.len() as u64 - 1)
+    }
+
+    fn block_at(&self, height: u64) -> Result<(BlockHash, Block)> {
+        let location = self.location(height)?;
+        Ok((location.hash, self.read_block(location)?))
+    }
+
+    fn hash_at(&self, height: u64) -> Result<BlockHash> {
+        Ok(self.location(height)?.hash)
+    }
+
+    fn header_at(&self, height: u64) -> Result<Header> {
+        let location = self.location(height)?;
+        let bytes = self.read_bytes(location, BLOCK_HEADER_SIZE)?;
+        bitcoin::consensus::deserialize(&bytes).map_err(|err| {
+            FelixError::BlockFile(format!(
+                "invalid header of block {}: {}",
+                location.hash, err
+            ))
+        })
+    }
+
+    /// Blocks below the first one in the files, if it isn't the genesis block, are missing
+    fn prune_height(&self) -> Result<Option<u64>> {
+        Ok(Some(self.base).filter(|base| *base > 0))
+    }
+}
+
+/// Blocks held in memory, e.g. canned blocks to drive `start_index` without a node
+pub struct MemorySource {
+    /// height of the first block of `blocks`
+    base: u64,
+    blocks: Vec<Block>,
+}
+
+impl MemorySource {
+    /// `blocks` are the chain from height `base` on, they aren't checked to link up
+    pub fn new(base: u64, blocks: Vec<Block>) -> Self {
+        Self { base, blocks }
+    }
+}
+
+impl BlockSource for MemorySource {
+    fn tip(&self) -> Result<u64> {
+        (self.base + self.blocks.len() as u64)
+            .checked_sub(1)
+            .ok_or_else(|| FelixError::BlockFile("no blocks in the source".into()))
+    }
+
+    fn block_at(&self, height: u64) -> Result<(BlockHash, Block)> {
+        let block = height
+            .checked_sub(self.base)
+            .and_then(|index| self.blocks.get(index as usize))
+            .ok_or_else(|| FelixError::BlockFile(format!("no block at height {}", height)))?;
+        Ok((block.block_hash(), block.clone()))
+    }
+
+    fn prune_height(&self) -> Result<Option<u64>> {
+        Ok(Some(self.base).filter(|base| *base > 0))
+    }
+}
+
+fn network_magic(network: Network) -> [u8; 4] {
+    match network {
+        Network::Mainnet => bitcoin::Network::Bitcoin,
+        Network::Testnet => bitcoin::Network::Testnet,
+        Network::Signet => bitcoin::Network::Signet,
+        Network::Regtest => bitcoin::Network::Regtest,
+    }
+    .magic()
+    .to_bytes()
+}
+
+/// The obfuscation key, None when there's no key file or the key is all zeroes
+fn read_xor_key(path: &Path) -> Result<Option<[u8; 8]>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let key: [u8; 8] = std::fs::read(path)?
+        .as_slice()
+        .try_into()
+        .map_err(|_| FelixError::BlockFile(format!("{} isn't 8 bytes", path.display())))?;
+    Ok(Some(key).filter(|key| *key != [0; 8]))
+}
+
+/// Undo the obfuscation of `bytes` read from `offset` in a block file
+fn unxor(bytes: &mut [u8], key: &[u8; 8], offset: u64) {
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte ^= key[((offset + i as u64) % 8) as usize];
+    }
+}
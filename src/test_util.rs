@@ -0,0 +1,105 @@
+//! Fixtures shared by the tests and benches, not part of the api
+
+use std::path::Path;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::block::{Header, Version};
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::{OP_CAT, OP_EQUAL};
+use bitcoin::script::Builder;
+use bitcoin::{
+    transaction, Amount, Block, BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence,
+    Transaction, TxIn, TxMerkleNode, TxOut, Txid, Witness,
+};
+
+use crate::{App, Auth, Config, Network, IN_MEMORY_DB_PATH};
+
+/// App over a temporary db indexing OP_CAT from `start_block`, without bitcoind
+pub fn test_app(start_block: u64) -> App {
+    test_app_at(Path::new(IN_MEMORY_DB_PATH), start_block)
+}
+
+/// `test_app` over the db at `db_path`
+pub fn test_app_at(db_path: &Path, start_block: u64) -> App {
+    App::new(Config {
+        bitcoind_urls: vec![],
+        bitcoind_auth: Auth::None,
+        network: Network::Regtest,
+        start_block: Some(start_block),
+        start_block_hash: None,
+        force: false,
+        db_path: db_path.to_path_buf(),
+        offline: true,
+        confirmations: 0,
+        rpc_concurrency: 2,
+        rpc_max_retries: 0,
+        parse_threads: 1,
+        opcodes: vec![OP_CAT],
+        strict_prevout_check: false,
+        with_annex: false,
+        scan_all_witness_items: false,
+        output_dir: std::env::temp_dir(),
+        compress: false,
+    })
+    .expect("open test app")
+}
+
+fn spend(tag: u32, witness: Witness) -> Transaction {
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), tag),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness,
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(1000),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    }
+}
+
+/// Script path spend of a tapscript concatenating its two witness elements, `tag` keeps the
+/// txids apart
+pub fn cat_spend(tag: u32) -> Transaction {
+    let script = Builder::new()
+        .push_opcode(OP_CAT)
+        .push_slice([1, 2])
+        .push_opcode(OP_EQUAL)
+        .into_script();
+    let mut control_block = vec![0xc0];
+    control_block.extend([2; 32]);
+    spend(
+        tag,
+        Witness::from_slice(&[vec![1], vec![2], script.into_bytes(), control_block]),
+    )
+}
+
+/// Key path spend, a lone schnorr signature
+pub fn key_path_spend(tag: u32) -> Transaction {
+    spend(tag, Witness::from_slice(&[vec![3; 64]]))
+}
+
+/// Blocks built on each other holding `txs`, `fork` tells apart blocks of competing chains
+pub fn chain(txs: Vec<Vec<Transaction>>, fork: u32) -> Vec<Block> {
+    let mut prev_blockhash = BlockHash::all_zeros();
+    txs.into_iter()
+        .map(|txdata| {
+            let block = Block {
+                header: Header {
+                    version: Version::TWO,
+                    prev_blockhash,
+                    merkle_root: TxMerkleNode::all_zeros(),
+                    time: 1_700_000_000,
+                    bits: CompactTarget::from_consensus(0x207fffff),
+                    nonce: fork,
+                },
+                txdata,
+            };
+            prev_blockhash = block.block_hash();
+            block
+        })
+        .collect()
+}
@@ -0,0 +1,167 @@
+//! Notifications POSTed to a webhook when a block with cat txs is persisted
+
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use bitcoin::Txid;
+use log::{debug, warn};
+use reqwest::blocking::Client;
+use serde::Serialize;
+
+/// Times a failed delivery is retried before the notification is dropped
+const WEBHOOK_RETRIES: u32 = 2;
+const WEBHOOK_RETRY_DELAY: Duration = Duration::from_secs(2);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Notifications waiting for delivery before indexing waits on the webhook
+const WEBHOOK_QUEUE: usize = 1000;
+
+/// Body of a notification
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockNotification {
+    pub height: u64,
+    pub count: usize,
+    pub txids: Vec<String>,
+}
+
+/// Delivers notifications in order from a single worker thread, so a slow or unreachable webhook
+/// never holds up indexing unless `WEBHOOK_QUEUE` of them are waiting. Dropping it delivers the
+/// queued notifications before returning
+pub struct Webhook {
+    sender: Option<SyncSender<BlockNotification>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Self {
+        let client = Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .expect("build webhook http client");
+        let url = url.to_string();
+        let (sender, receiver) = mpsc::sync_channel(WEBHOOK_QUEUE);
+        let worker = std::thread::spawn(move || {
+            for notification in receiver {
+                deliver(&client, &url, &notification);
+            }
+        });
+        Self {
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+
+    /// Queue the txs of the block at `height` for delivery, waiting for room when the queue is
+    /// full
+    pub fn notify(&self, height: u64, txids: &[Txid]) {
+        let notification = BlockNotification {
+            height,
+            count: txids.len(),
+            txids: txids.iter().map(Txid::to_string).collect(),
+        };
+        if let Some(sender) = &self.sender {
+            // only fails once the worker is gone, which takes a panic
+            if sender.send(notification).is_err() {
+                warn!(
+                    "webhook worker stopped, dropping notification of height {}",
+                    height
+                );
+            }
+        }
+    }
+}
+
+impl Drop for Webhook {
+    fn drop(&mut self) {
+        // closing the channel ends the worker once it went through the queue
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                warn!("webhook worker panicked");
+            }
+        }
+    }
+}
+
+/// POST a notification, retrying failed attempts. Failures are only logged
+fn deliver(client: &Client, url: &str, notification: &BlockNotification) {
+    let height = notification.height;
+    for attempt in 0..=WEBHOOK_RETRIES {
+        if attempt > 0 {
+            std::thread::sleep(WEBHOOK_RETRY_DELAY);
+        }
+        match client
+            .post(url)
+            .json(notification)
+            .send()
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => {
+                debug!("notified webhook of height {}", height);
+                return;
+            }
+            Err(err) => warn!(
+                "webhook delivery for height {} failed (attempt {}/{}): {}",
+                height,
+                attempt + 1,
+                WEBHOOK_RETRIES + 1,
+                err
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    #[test]
+    fn delivers_queued_notifications_on_drop() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(vec![]));
+        let server_received = received.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                server_received
+                    .lock()
+                    .unwrap()
+                    .push(body["height"].as_u64().unwrap());
+                stream
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .unwrap();
+            }
+        });
+
+        let webhook = Webhook::new(&url);
+        for height in 0..3 {
+            webhook.notify(height, &[Txid::all_zeros()]);
+        }
+        drop(webhook);
+        assert_eq!(*received.lock().unwrap(), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,186 @@
+//! Parsing of taproot script path spends out of input witnesses
+
+use bitcoin::taproot::{
+    TAPROOT_ANNEX_PREFIX, TAPROOT_CONTROL_BASE_SIZE, TAPROOT_CONTROL_MAX_SIZE,
+    TAPROOT_CONTROL_NODE_SIZE, TAPROOT_LEAF_MASK, TAPROOT_LEAF_TAPSCRIPT,
+};
+use bitcoin::{Script, Witness};
+
+/// The parts of a script path spend, borrowed from its witness
+#[derive(Debug, Clone, Copy)]
+pub struct TapscriptSpend<'a> {
+    /// leaf version from the first byte of the control block
+    pub leaf_version: u8,
+    pub script: &'a Script,
+    pub control_block: &'a [u8],
+    /// last witness element when it starts with 0x50, including that prefix
+    pub annex: Option<&'a [u8]>,
+    /// index of the tapscript among the witness elements
+    pub witness_index: usize,
+}
+
+/// A witness element used as a script, either the tapscript of a script path spend or, when
+/// scanning every element, any element that parses as a script
+#[derive(Debug, Clone, Copy)]
+pub struct WitnessScript<'a> {
+    pub script: &'a Script,
+    /// index of the element among the witness elements
+    pub witness_index: usize,
+    /// leaf version of the control block, only for the tapscript of a script path spend
+    pub leaf_version: Option<u8>,
+    /// annex of the script path spend the tapscript belongs to
+    pub annex: Option<&'a [u8]>,
+}
+
+impl<'a> From<TapscriptSpend<'a>> for WitnessScript<'a> {
+    fn from(spend: TapscriptSpend<'a>) -> Self {
+        Self {
+            script: spend.script,
+            witness_index: spend.witness_index,
+            leaf_version: Some(spend.leaf_version),
+            annex: spend.annex,
+        }
+    }
+}
+
+/// Select the tapscript of a script path spend: the second to last witness element once the
+/// annex (a last element starting with 0x50) has been stripped. Witnesses whose last element isn't
+/// a tapscript control block, like key path spends, have no tapscript. The control block only
+/// shows up in taproot spends so this also vouches for the prevout being P2TR
+pub fn parse(witness: &Witness) -> Option<TapscriptSpend<'_>> {
+    parse_script_path(witness).filter(|spend| spend.leaf_version == TAPROOT_LEAF_TAPSCRIPT)
+}
+
+/// Like `parse`, but for a script path spend of any leaf version, including the ones reserved
+/// for future soft forks whose script isn't a tapscript
+pub fn parse_script_path(witness: &Witness) -> Option<TapscriptSpend<'_>> {
+    let mut elements: Vec<&[u8]> = witness.iter().collect();
+    let annex = match elements.last() {
+        Some(last) if elements.len() >= 2 && last.first() == Some(&TAPROOT_ANNEX_PREFIX) => {
+            elements.pop()
+        }
+        _ => None,
+    };
+    // A script path spend needs at least the tapscript and the control block
+    let [.., script, control_block] = elements[..] else {
+        return None;
+    };
+    if !is_control_block(control_block) {
+        return None;
+    }
+
+    Some(TapscriptSpend {
+        leaf_version: control_block[0] & TAPROOT_LEAF_MASK,
+        script: Script::from_bytes(script),
+        control_block,
+        annex,
+        witness_index: elements.len() - 2,
+    })
+}
+
+/// Every witness element that parses as a non empty script. The tapscript of a script path spend
+/// comes with its spend details, its control block and annex are skipped. Other elements, like
+/// a P2WSH witness script, only parse when every push in them is complete, but data such as
+/// signatures can still happen to parse as a script
+pub fn scripts(witness: &Witness) -> Vec<WitnessScript<'_>> {
+    let spend = parse(witness);
+    let mut scripts = vec![];
+    for (index, element) in witness.iter().enumerate() {
+        if let Some(spend) = spend {
+            if index == spend.witness_index {
+                scripts.push(WitnessScript::from(spend));
+                continue;
+            }
+            // the control block and annex follow the tapscript
+            if index > spend.witness_index {
+                continue;
+            }
+        }
+        let script = Script::from_bytes(element);
+        if script.is_empty()
+            || script
+                .instructions()
+                .any(|instruction| instruction.is_err())
+        {
+            continue;
+        }
+        scripts.push(WitnessScript {
+            script,
+            witness_index: index,
+            leaf_version: None,
+            annex: None,
+        });
+    }
+
+    scripts
+}
+
+/// Number of witness elements once the annex has been stripped
+pub fn item_count(witness: &Witness) -> usize {
+    match witness.last() {
+        Some(last) if witness.len() >= 2 && last.first() == Some(&TAPROOT_ANNEX_PREFIX) => {
+            witness.len() - 1
+        }
+        _ => witness.len(),
+    }
+}
+
+/// A key path spend carries nothing but a 64 byte schnorr signature, or 65 with an explicit
+/// sighash type, besides the annex. Without the prevout this can't tell it from a P2WSH whose
+/// witness script happens to be such a single element
+pub fn is_key_path_spend(witness: &Witness) -> bool {
+    item_count(witness) == 1
+        && witness
+            .nth(0)
+            .is_some_and(|sig| matches!(sig.len(), 64 | 65))
+}
+
+/// A control block is the leaf version and output key parity byte, the internal key and up to
+/// 128 merkle path nodes. The leaf version can't be 0x50, which would make it an annex
+fn is_control_block(control_block: &[u8]) -> bool {
+    control_block.len() >= TAPROOT_CONTROL_BASE_SIZE
+        && control_block.len() <= TAPROOT_CONTROL_MAX_SIZE
+        && (control_block.len() - TAPROOT_CONTROL_BASE_SIZE)
+            .is_multiple_of(TAPROOT_CONTROL_NODE_SIZE)
+        && control_block[0] & TAPROOT_LEAF_MASK != TAPROOT_ANNEX_PREFIX
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_empty_witness() {
+        let witness = Witness::new();
+        assert!(parse(&witness).is_none());
+        assert!(scripts(&witness).is_empty());
+        assert_eq!(item_count(&witness), 0);
+    }
+
+    #[test]
+    fn parse_single_element_witness() {
+        // a key path signature, and a lone element that looks like an annex
+        for element in [vec![1; 64], vec![TAPROOT_ANNEX_PREFIX, 1]] {
+            let witness = Witness::from_slice(&[element]);
+            assert!(parse(&witness).is_none());
+            assert_eq!(item_count(&witness), 1);
+        }
+    }
+
+    #[test]
+    fn parse_script_path_of_any_leaf_version() {
+        let spend_with = |leaf_version: u8| {
+            let mut control_block = vec![leaf_version | 1];
+            control_block.extend([2; 32]);
+            Witness::from_slice(&[vec![0x7e], control_block])
+        };
+        let tapscript = spend_with(TAPROOT_LEAF_TAPSCRIPT);
+        assert_eq!(parse(&tapscript).unwrap().leaf_version, 0xc0);
+        assert_eq!(parse_script_path(&tapscript).unwrap().leaf_version, 0xc0);
+
+        // a reserved leaf version is a script path spend, but not a tapscript
+        let reserved = spend_with(0xc2);
+        assert!(parse(&reserved).is_none());
+        assert_eq!(parse_script_path(&reserved).unwrap().leaf_version, 0xc2);
+    }
+}
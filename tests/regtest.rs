@@ -0,0 +1,138 @@
+//! End to end indexing against a regtest bitcoind, started through the `bitcoind` crate. Ignored
+//! by default since it needs a bitcoind binary, run it with
+//! `BITCOIND_EXE=/path/to/bitcoind cargo test --test regtest -- --ignored`
+
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::opcodes::all::{OP_CAT, OP_EQUAL};
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Keypair, Secp256k1, SecretKey};
+use bitcoin::taproot::{LeafVersion, TaprootBuilder};
+use bitcoin::{
+    absolute, transaction, Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn,
+    TxOut, Witness,
+};
+use bitcoincore_rpc::RpcApi;
+use bitcoind::BitcoinD;
+use felix::{App, Auth, Config, IndexOptions, Network};
+use serde_json::json;
+use tempfile::TempDir;
+
+const FUNDING: Amount = Amount::from_sat(100_000);
+const FEE: Amount = Amount::from_sat(1_000);
+
+fn start_bitcoind() -> BitcoinD {
+    let mut conf = bitcoind::Conf::default();
+    // --strict-prevout-check looks the funding tx up
+    conf.args.push("-txindex");
+    BitcoinD::with_conf(bitcoind::exe_path().expect("bitcoind binary"), &conf)
+        .expect("start bitcoind")
+}
+
+fn felix(bitcoind: &BitcoinD, db: &TempDir) -> App {
+    App::new(Config {
+        bitcoind_urls: vec![bitcoind.rpc_url()],
+        bitcoind_auth: Auth::CookieFile(bitcoind.params.cookie_file.clone()),
+        network: Network::Regtest,
+        start_block: Some(0),
+        start_block_hash: None,
+        force: false,
+        db_path: db.path().to_path_buf(),
+        offline: false,
+        confirmations: 0,
+        rpc_concurrency: 4,
+        rpc_max_retries: 0,
+        parse_threads: 1,
+        opcodes: vec![OP_CAT],
+        strict_prevout_check: true,
+        with_annex: false,
+        scan_all_witness_items: false,
+        output_dir: db.path().to_path_buf(),
+        compress: false,
+    })
+    .expect("open felix")
+}
+
+#[test]
+#[ignore = "needs a bitcoind binary, see the module docs"]
+fn indexes_cat_spend_on_regtest() {
+    let bitcoind = start_bitcoind();
+    let wallet = bitcoind.create_wallet("felix").expect("create wallet");
+    let miner = wallet.get_new_address(None, None).unwrap().assume_checked();
+    wallet.generate_to_address(101, &miner).unwrap();
+
+    // A taproot output with a single OP_CAT leaf. OP_CAT is an OP_SUCCESS in tapscript, so the
+    // spend is consensus valid but non standard and has to be mined with generateblock
+    let secp = Secp256k1::new();
+    let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+    let (internal_key, _) = keypair.x_only_public_key();
+    let script = Builder::new()
+        .push_opcode(OP_CAT)
+        .push_slice(b"felix")
+        .push_opcode(OP_EQUAL)
+        .into_script();
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(0, script.clone())
+        .unwrap()
+        .finalize(&secp, internal_key)
+        .unwrap();
+    let address = Address::p2tr_tweaked(spend_info.output_key(), bitcoin::Network::Regtest);
+
+    let funding_txid = wallet
+        .send_to_address(&address, FUNDING, None, None, None, None, None, None)
+        .unwrap();
+    wallet.generate_to_address(1, &miner).unwrap();
+    let funding = bitcoind
+        .client
+        .get_raw_transaction(&funding_txid, None)
+        .unwrap();
+    let vout = funding
+        .output
+        .iter()
+        .position(|output| output.script_pubkey == address.script_pubkey())
+        .expect("funding output") as u32;
+
+    let control_block = spend_info
+        .control_block(&(script.clone(), LeafVersion::TapScript))
+        .unwrap();
+    let spend = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(funding_txid, vout),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::from_slice(&[
+                b"fe".to_vec(),
+                b"lix".to_vec(),
+                script.into_bytes(),
+                control_block.serialize(),
+            ]),
+        }],
+        output: vec![TxOut {
+            value: FUNDING - FEE,
+            script_pubkey: miner.script_pubkey(),
+        }],
+    };
+    bitcoind
+        .client
+        .call::<serde_json::Value>(
+            "generateblock",
+            &[json!(miner.to_string()), json!([serialize_hex(&spend)])],
+        )
+        .expect("mine the cat spend");
+    // indexing stops before the tip
+    wallet.generate_to_address(1, &miner).unwrap();
+
+    let db = tempfile::tempdir().unwrap();
+    let app = felix(&bitcoind, &db);
+    app.start_index(app.block_source().unwrap(), &IndexOptions::default())
+        .expect("index");
+
+    assert_eq!(app.get_total_cat_txs().unwrap(), 1);
+    let tx_ext = app
+        .get_tx(&spend.compute_txid())
+        .unwrap()
+        .expect("indexed cat spend");
+    assert_eq!(tx_ext.opcodes, ["OP_CAT"]);
+    assert_eq!(tx_ext.height, 103);
+}